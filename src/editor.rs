@@ -0,0 +1,87 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::env;
+use std::path::PathBuf;
+use rustyline::{Context, Helper};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use crate::gramma::environment::Environment;
+
+//历史记录文件名 存放在用户主目录下
+const HISTORY_FILE: &str = ".tiny_interpreter_history";
+
+//历史记录文件路径 找不到主目录时不保存历史记录
+pub fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")).map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+//REPL行编辑辅助 用全局环境中已注册的名字(原始函数、常量和用户定义)做tab补全
+pub struct ReplHelper {
+    env: Rc<RefCell<Environment>>,
+}
+
+impl ReplHelper {
+    pub fn new(env: Rc<RefCell<Environment>>) -> Self {
+        ReplHelper { env }
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        //光标前的标识符片段(标识符只由ascii字母、数字和下划线组成)
+        let start = line[..pos].char_indices().rev()
+            .find(|(_, c)| !(c.is_ascii_alphanumeric() || *c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &line[start..pos];
+        //数字开头的不是标识符
+        if prefix.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok((pos, vec![]));
+        }
+        let mut names: Vec<String> = self.env.borrow().iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        names.sort();
+        Ok((start, names))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod test {
+    use rustyline::Context;
+    use rustyline::completion::Completer;
+    use rustyline::history::DefaultHistory;
+    use crate::gramma::primitive::create_global_environment;
+    use super::ReplHelper;
+
+    fn complete(line: &str, pos: usize) -> (usize, Vec<String>) {
+        let helper = ReplHelper::new(create_global_environment(&[]));
+        let history = DefaultHistory::new();
+        helper.complete(line, pos, &Context::new(&history)).unwrap()
+    }
+
+    #[test]
+    fn complete_prefix() {
+        assert_eq!(complete("li", 2), (0, vec!["linespace".to_string()]));
+        //数字开头的不是标识符
+        assert_eq!(complete("1e", 2), (2, vec![]));
+        //只取光标之前的片段 前面的非ascii字符按字节位置计算
+        assert_eq!(complete("\"é\" + li(", 9), (7, vec!["linespace".to_string()]));
+        assert_eq!(complete("x = sqr + 1", 7), (4, vec!["sqrt".to_string()]));
+    }
+}
@@ -2,39 +2,77 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use crate::gramma::token::Op;
 use crate::gramma::environment::Environment;
+use crate::gramma::lexer::Span;
+use crate::gramma::evaluator::Signal;
+use crate::gramma::error::{ErrorKind, RuntimeError};
 
 //抽象语法树节点
 //其实只有在evalue lambda时用到了clone
+//可能在求值时出错的节点带有其在源码中的位置Span 用于错误信息
 #[derive(Clone, Debug)]
 pub enum ASTNode {
     //字面量
     Literal(ASTValue),
     //变量
-    Var(String),
-    //一元运算
-    Unitary(Op, Box<ASTNode>),
-    //二元运算
-    Binary(Op, Box<ASTNode>, Box<ASTNode>),
-    //数组索引
-    Index(Box<ASTNode>, Box<ASTNode>),
-    //调用f(x, y), ...
-    Apply(Box<ASTNode>, Vec<ASTNode>),
+    Var(String, Span),
+    //一元运算(位置为运算符)
+    Unitary(Op, Box<ASTNode>, Span),
+    //二元运算(位置为运算符)
+    Binary(Op, Box<ASTNode>, Box<ASTNode>, Span),
+    //数组索引(位置为整个索引表达式)
+    Index(Box<ASTNode>, Box<ASTNode>, Span),
+    //调用f(x, y), ...(位置为整个调用表达式)
+    Apply(Box<ASTNode>, Vec<ASTNode>, Span),
     //数组[1, 2, 3, 4, 5, 6]
     Array(Vec<ASTNode>),
-    //匿名函数
-    Lambda(Vec<String>, Box<ASTNode>),
+    //映射{key: value, "other key": value, ...}
+    Map(Vec<(String, ASTNode)>),
+    //匿名函数(函数体用Rc包裹 生成函数对象时无需拷贝整棵子树)
+    Lambda(Vec<String>, Rc<ASTNode>),
     //语句块
     Block(Vec<ASTNode>),
     //条件表达式 If(条件)语句块 大于等于0个elseif(条件)语句块 0或1个else语句块
     Cond(Box<(ASTNode, ASTNode)>, Vec<(ASTNode, ASTNode)>, Option<Box<ASTNode>>),
+    //while循环 While(条件)语句块
+    While(Box<ASTNode>, Box<ASTNode>),
+    //for循环 For(循环变量 in 数组)语句块
+    For(String, Box<ASTNode>, Box<ASTNode>),
+    //跳出循环(位置为break关键字)
+    Break(Span),
+    //进入下一次循环(位置为continue关键字)
+    Continue(Span),
+    //从函数中返回(return;时返回值节点为Empty)
+    Return(Box<ASTNode>),
+    //捕获错误 Try(语句块 错误变量名 catch语句块)
+    Try(Box<ASTNode>, String, Box<ASTNode>),
+    //抛出错误(位置为throw关键字)
+    Throw(Box<ASTNode>, Span),
     //定义(true)赋值(false)数值/函数变量
-    Assign(String, Box<ASTNode>, bool),
+    //左值为变量Var或者以变量为根的索引链Index 例如a、arr[i]、m[i][j]
+    Assign(Box<ASTNode>, Box<ASTNode>, bool),
+    //复合赋值 a += expr、arr[i] *= expr 左值规则与赋值相同
+    CompoundAssign(Op, Box<ASTNode>, Box<ASTNode>),
     //空返回值语句(带了分号)
     Void(Box<ASTNode>),
     //空语句
     Empty,
 }
 
+impl ASTNode {
+    //节点在源码中的位置 不带位置的节点返回None
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ASTNode::Var(_, span)
+            | ASTNode::Unitary(_, _, span)
+            | ASTNode::Binary(_, _, _, span)
+            | ASTNode::Index(_, _, span)
+            | ASTNode::Apply(_, _, span)
+            | ASTNode::Throw(_, span) => Some(*span),
+            _ => None,
+        }
+    }
+}
+
 //抽象语法树节点值
 #[derive(Clone)]
 //AST求值结果
@@ -43,9 +81,13 @@ pub enum ASTValue {
     Number(f64),
     //布尔变量
     Boolean(bool),
+    //字符串(不可变 用Rc<str>使得拷贝廉价)
+    String(Rc<str>),
     //数组类型变量(套一层Rc的原因是[]不定长)
     //不用box的原因是env的get函数会拷贝返回
     Array(Rc<[ASTValue]>),
+    //映射(按插入顺序保存键值对 键值对数量一般很少 线性查找即可)
+    Map(Rc<Vec<(String, ASTValue)>>),
     //函数对象(套一层Rc的原因是Trait类似于C++基类 无实体)
     Function(Rc<dyn Callable>),
 }
@@ -53,25 +95,60 @@ pub enum ASTValue {
 //函数对象trait
 pub trait Callable {
     fn name(&self) -> Option<&str>;
-    fn call(&self, args: &[ASTValue], env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String>;
+    fn call(&self, args: &[ASTValue], env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError>;
+    //执行函数但不展开函数体尾部位置的调用(以TailCall信号返回 由调用方循环执行)
+    fn call_tail(&self, args: &[ASTValue], env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+        Ok(self.call(args, env)?)
+    }
+    //闭包捕获的环境 原始函数没有
+    fn env(&self) -> Option<&Rc<RefCell<Environment>>> {
+        None
+    }
 }
 
 //结果类型转换函数
 impl ASTValue {
-    pub fn f64(&self) -> Result<f64, String> {
+    pub fn f64(&self) -> Result<f64, RuntimeError> {
         match self {
             ASTValue::Number(x) => Ok(*x),
             ASTValue::Boolean(true) => Ok(1.0),
             ASTValue::Boolean(false) => Ok(0.0),
-            _ => raise!("illegal casting to f64"),
+            _ => raise!(ErrorKind::WrongType { expected: "number", found: self.type_name() }),
         }
     }
 
-    pub fn boolean(&self) -> Result<bool, String> {
+    pub fn boolean(&self) -> Result<bool, RuntimeError> {
         match self {
             ASTValue::Number(x) => Ok(*x != 0.0),
             ASTValue::Boolean(x) => Ok(*x),
-            _ => raise!("illegal casting to boolean"),
+            _ => raise!(ErrorKind::WrongType { expected: "boolean", found: self.type_name() }),
+        }
+    }
+
+    //在映射中查找键对应的值
+    pub fn get_key(&self, key: &str) -> Option<&ASTValue> {
+        match self {
+            ASTValue::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    //类型名称
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ASTValue::Number(_) => "number",
+            ASTValue::Boolean(_) => "boolean",
+            ASTValue::String(_) => "string",
+            ASTValue::Array(_) => "array",
+            ASTValue::Map(_) => "map",
+            ASTValue::Function(_) => "function",
+        }
+    }
+
+    pub fn string(&self) -> Result<Rc<str>, RuntimeError> {
+        match self {
+            ASTValue::String(s) => Ok(s.clone()),
+            _ => raise!(ErrorKind::WrongType { expected: "string", found: self.type_name() }),
         }
     }
 }
@@ -83,12 +160,77 @@ impl fmt::Debug for ASTValue {
         match &self {
             ASTValue::Number(value) => write!(f, "{}", value),
             ASTValue::Boolean(value) => write!(f, "{}", value),
+            ASTValue::String(value) => write!(f, "{:?}", value),
             ASTValue::Array(values) => write!(f, "{:?}", values),
+            ASTValue::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {:?}", format_key(key), value)?;
+                }
+                write!(f, "}}")
+            },
             ASTValue::Function(fun) => write!(f, "fn-{}", fun.name().unwrap_or("anonymous")),
         }
     }
 }
 
+//值的显示格式(REPL输出、字符串拼接时使用)
+//字符串本身原样输出 作为数组元素时带引号输出
+impl fmt::Display for ASTValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            ASTValue::Number(value) => write!(f, "{}", value),
+            ASTValue::Boolean(value) => write!(f, "{}", value),
+            ASTValue::String(value) => write!(f, "{}", value),
+            ASTValue::Function(_) => write!(f, "lambda"),
+            ASTValue::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_element(f, element)?;
+                }
+                write!(f, "]")
+            },
+            ASTValue::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", format_key(key))?;
+                    write_element(f, value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+//容器中的元素 字符串带引号输出
+fn write_element(f: &mut fmt::Formatter, element: &ASTValue) -> fmt::Result {
+    match element {
+        ASTValue::String(value) => write!(f, "{:?}", value),
+        _ => write!(f, "{}", element),
+    }
+}
+
+//映射的键 合法标识符原样输出 否则带引号输出
+fn format_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let is_symbol = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_symbol {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
 //用于单元测试assert比较
 use std::cmp;
 impl cmp::PartialOrd for ASTValue {
@@ -102,7 +244,13 @@ impl cmp::PartialOrd for ASTValue {
                 }
             }
             (ASTValue::Boolean(x), ASTValue::Boolean(y)) => x.partial_cmp(y),
+            (ASTValue::String(x), ASTValue::String(y)) => x.partial_cmp(y),
             (ASTValue::Array(x), ASTValue::Array(y)) => x.partial_cmp(y),
+            //映射之间只比较是否相等 与键的顺序无关
+            (ASTValue::Map(x), ASTValue::Map(y)) => {
+                let equal = x.len() == y.len() && x.iter().all(|(key, value)| other.get_key(key) == Some(value));
+                if equal { Some(cmp::Ordering::Equal) } else { None }
+            },
             _ => None,
         }
     }
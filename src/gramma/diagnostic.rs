@@ -0,0 +1,36 @@
+use crate::gramma::lexer::Span;
+
+//由字符位置计算行号和列号(都从1开始)
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let mut line = 1;
+    let mut col = 1;
+    for c in source.chars().take(offset) {
+        if c == '\n' {
+            line += 1;
+            col = 1;
+        } else {
+            col += 1;
+        }
+    }
+    (line, col)
+}
+
+//生成带有源码行和^^^标注的错误信息
+//  title
+//   --> 3:9
+//    |
+//  3 | let x = (1 + 2;
+//    |               ^
+//跨越多行的位置只标注到第一行的行尾
+pub fn annotate(source: &str, span: Span, title: &str) -> String {
+    let (line, col) = line_col(source, span.0);
+    let text = source.split('\n').nth(line - 1).unwrap_or("").trim_end_matches('\r');
+    let width = text.chars().count();
+    //至少标注一个字符 输入结尾处的错误标注在最后一个字符之后
+    let len = span.1.saturating_sub(span.0).min((width + 1).saturating_sub(col)).max(1);
+    //标注前的空白保留制表符 使^与源码对齐
+    let indent: String = text.chars().take(col - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+    let gutter = " ".repeat(line.to_string().len());
+    format!("{}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}{}",
+        title, gutter, line, col, gutter, line, text, gutter, indent, "^".repeat(len))
+}
@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use crate::gramma::ast::ASTValue;
 
 //环境表
+#[derive(Clone)]
 pub struct Environment {
     //变量表
     table: HashMap<String, ASTValue>,
@@ -17,7 +18,7 @@ impl Environment {
     pub fn new(parent: Option<Rc<RefCell<Environment>>>) -> Self {
         Environment {
             table: HashMap::new(),
-            parent: parent,
+            parent,
         }
     }
 
@@ -35,15 +36,47 @@ impl Environment {
     //会一直往父环境中搜索
     pub fn set(&mut self, key: &str, val: ASTValue) {
         //先找当前环境
-        if self.table.get(key).is_some() {
+        if self.table.contains_key(key) {
             self.table.insert(key.into(), val);
         } else if self.parent.is_some() {
             self.parent.clone().unwrap().borrow_mut().set(key, val);
         }
     }
 
+    //就地修改变量的值 会一直往父环境中搜索 变量不存在时返回None
+    pub fn modify<F, R>(&mut self, key: &str, fun: F) -> Option<R>
+    where
+        F: FnOnce(&mut ASTValue) -> R,
+    {
+        if let Some(x) = self.table.get_mut(key) {
+            Some(fun(x))
+        } else if let Some(parent) = &self.parent {
+            parent.borrow_mut().modify(key, fun)
+        } else {
+            None
+        }
+    }
+
+    //遍历当前环境中的变量(不包括父环境)
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ASTValue)> {
+        self.table.iter()
+    }
+
     //在当前环境注册新的变量
     pub fn regist(&mut self, key: &str, val: ASTValue) {
         self.table.insert(key.into(), val);
     }
+
+    //作用域结束时调用
+    //作用域中定义的函数捕获了作用域自身的环境 两者互相引用 环境只被这些函数引用时已经无法再访问
+    //此时清空变量表打破循环引用 否则每次执行都会泄漏一个环境
+    pub fn release(env: Rc<RefCell<Environment>>) {
+        let owned = env.borrow().table.values().filter(|value| match value {
+            ASTValue::Function(fun) => Rc::strong_count(fun) == 1 && fun.env().is_some_and(|captured| Rc::ptr_eq(captured, &env)),
+            _ => false,
+        }).count();
+        if owned > 0 && Rc::strong_count(&env) == owned + 1 {
+            env.borrow_mut().table.clear();
+        }
+    }
 }
\ No newline at end of file
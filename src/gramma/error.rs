@@ -0,0 +1,175 @@
+use std::fmt;
+use crate::gramma::lexer::Span;
+use crate::gramma::diagnostic::{annotate, line_col};
+
+//求值错误的种类 携带出错的运算符、名字或者类型
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    //运算符不支持的操作数类型 单目运算只有一个类型
+    TypeMismatch { op: String, types: Vec<&'static str> },
+    //需要某种类型的值却得到了另一种
+    WrongType { expected: &'static str, found: &'static str },
+    //变量未定义
+    UndefinedName(String),
+    //同一作用域内重复定义变量
+    Redefinition(String),
+    //实参数量与形参不一致 函数名为None时由原始函数在返回前补上
+    Arity { name: Option<String>, expected: usize, found: usize },
+    //数组或字符串下标越界
+    IndexOutOfBounds { index: f64, len: usize },
+    //映射中不存在的键
+    KeyNotFound(String),
+    //调用了不是函数的值
+    NotCallable(&'static str),
+    //超出最大调用深度
+    StackOverflow { name: Option<String>, depth: usize },
+    //需要值的地方出现了无返回值的语句 记录出现的位置(操作数、条件、实参等)
+    NoValue(&'static str),
+    //循环之外的break或者continue
+    OutsideLoop(&'static str),
+    //实参的值不在允许的范围内 记录原因
+    InvalidArgument(&'static str),
+    //不能作为赋值或者定义左值的表达式 记录原因
+    InvalidTarget(&'static str),
+    //用户通过error(message)或者throw抛出的错误 kind为catch时看到的错误种类
+    User { kind: String, message: String },
+}
+
+//调用栈中的一层 函数名(匿名函数为<lambda>)以及调用表达式的位置
+//由原始函数(例如map)调用的函数没有调用表达式 位置为None
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    pub span: Option<Span>,
+}
+
+//求值错误 span为出错的语法树节点在源码中的位置
+//trace为出错时仍在执行的函数 最内层的在前 随着错误向外传递逐层添加
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub span: Option<Span>,
+    pub trace: Vec<Frame>,
+}
+
+impl RuntimeError {
+    //记录出错位置 已经有位置时保留最内层的位置
+    pub fn at(mut self, span: Span) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    //错误离开用户定义的函数 此时还不知道调用位置
+    pub fn in_function(mut self, name: Option<&str>) -> Self {
+        self.trace.push(Frame {
+            name: name.unwrap_or("<lambda>").to_string(),
+            span: None,
+        });
+        self
+    }
+
+    //错误离开调用表达式 补上被调用函数的调用位置
+    //用户定义的函数已经在in_function中添加了这一层 原始函数和尾调用则在这里添加
+    pub fn called_at(mut self, name: Option<&str>, span: Span) -> Self {
+        let name = name.unwrap_or("<lambda>");
+        match self.trace.last_mut() {
+            Some(frame) if frame.span.is_none() && frame.name == name => frame.span = Some(span),
+            _ => self.trace.push(Frame { name: name.to_string(), span: Some(span) }),
+        }
+        self
+    }
+
+    //带有源码行和^^^标注的完整错误信息 没有位置时只输出一行
+    //其后列出调用栈 递归调用中连续重复的层合并为一行
+    pub fn render(&self, source: &str) -> String {
+        let title = format!("evaluate error: {}", self);
+        let mut text = match self.span {
+            Some(span) => annotate(source, span, &title),
+            None => title,
+        };
+        if !self.trace.is_empty() {
+            text.push_str("\ncall stack (most recent call first):");
+        }
+        let mut i = 0;
+        while i < self.trace.len() {
+            let frame = &self.trace[i];
+            let repeated = self.trace[i + 1..].iter().take_while(|next| *next == frame).count();
+            i += repeated + 1;
+            match (frame.span, self.trace.get(i)) {
+                (Some(span), _) => {
+                    let (line, col) = line_col(source, span.0);
+                    text.push_str(&format!("\n  {} called at {}:{}", frame.name, line, col));
+                },
+                //没有调用位置的函数由外面一层的原始函数调用
+                (None, Some(caller)) => text.push_str(&format!("\n  {} called by {}", frame.name, caller.name)),
+                (None, None) => text.push_str(&format!("\n  {}", frame.name)),
+            }
+            if repeated > 0 {
+                text.push_str(&format!("\n  ... repeated {} more times", repeated));
+            }
+        }
+        text
+    }
+}
+
+impl ErrorKind {
+    //错误种类的名称 catch (e)中的e.kind
+    pub fn name(&self) -> &str {
+        match self {
+            ErrorKind::TypeMismatch { .. } => "type_mismatch",
+            ErrorKind::WrongType { .. } => "wrong_type",
+            ErrorKind::UndefinedName(_) => "undefined_name",
+            ErrorKind::Redefinition(_) => "redefinition",
+            ErrorKind::Arity { .. } => "arity",
+            ErrorKind::IndexOutOfBounds { .. } => "index_out_of_bounds",
+            ErrorKind::KeyNotFound(_) => "key_not_found",
+            ErrorKind::NotCallable(_) => "not_callable",
+            ErrorKind::StackOverflow { .. } => "stack_overflow",
+            ErrorKind::NoValue(_) => "no_value",
+            ErrorKind::OutsideLoop(_) => "outside_loop",
+            ErrorKind::InvalidArgument(_) => "invalid_argument",
+            ErrorKind::InvalidTarget(_) => "invalid_target",
+            ErrorKind::User { kind, .. } => kind,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::TypeMismatch { op, types } => write!(f, "`{}` can not be applied to {}", op, types.join(" and ")),
+            ErrorKind::WrongType { expected, found } => write!(f, "expected {} but found {}", expected, found),
+            ErrorKind::UndefinedName(name) => write!(f, "undefined variable `{}`", name),
+            ErrorKind::Redefinition(name) => write!(f, "variable `{}` is already defined", name),
+            ErrorKind::Arity { name, expected, found } => {
+                write!(f, "`{}` expects {} argument{} but got {}",
+                    name.as_deref().unwrap_or("<lambda>"), expected, if *expected == 1 { "" } else { "s" }, found)
+            },
+            ErrorKind::IndexOutOfBounds { index, len } => write!(f, "index {} out of bounds for length {}", index, len),
+            ErrorKind::KeyNotFound(key) => write!(f, "key not found: {}", key),
+            ErrorKind::NotCallable(found) => write!(f, "{} is not callable", found),
+            ErrorKind::StackOverflow { name, depth } => {
+                write!(f, "stack overflow: maximum call depth {} exceeded in {}", depth, name.as_deref().unwrap_or("<lambda>"))
+            },
+            ErrorKind::NoValue(what) => write!(f, "{} has no value", what),
+            ErrorKind::OutsideLoop(what) => write!(f, "{} outside of loop", what),
+            ErrorKind::InvalidArgument(reason) => write!(f, "{}", reason),
+            ErrorKind::InvalidTarget(reason) => write!(f, "{}", reason),
+            ErrorKind::User { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl From<ErrorKind> for RuntimeError {
+    fn from(kind: ErrorKind) -> Self {
+        RuntimeError { kind, span: None, trace: vec![] }
+    }
+}
@@ -1,12 +1,179 @@
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use crate::gramma::token::Op;
+use crate::gramma::lexer::Span;
+use crate::gramma::error::{ErrorKind, RuntimeError};
 use crate::gramma::environment::Environment;
-use crate::gramma::ast::{ASTNode, ASTValue};
+use crate::gramma::ast::{ASTNode, ASTValue, Callable};
 use crate::gramma::usrfun::UsrDefFun;
 
+//求值过程中的非正常控制流 借助Result的Err通道和?运算符逐层向上传递
+pub enum Signal {
+    //求值错误
+    Error(RuntimeError),
+    //跳出循环 记录break的位置 游离在循环外时用于报错
+    Break(Span),
+    //进入下一次循环 记录continue的位置
+    Continue(Span),
+    //从函数中返回
+    Return(Option<ASTValue>),
+    //尾部位置的函数调用 交由外层的蹦床循环执行 不再占用调用栈
+    //同时记录调用表达式的位置
+    TailCall(Rc<dyn Callable>, Vec<ASTValue>, Span),
+}
+
+impl Signal {
+    //为尚未定位的错误记录出错位置 其余信号原样返回
+    fn at(self, span: Span) -> Self {
+        match self {
+            Signal::Error(err) => Signal::Error(err.at(span)),
+            signal => signal,
+        }
+    }
+
+    //记录语法树节点的位置 节点不带位置时原样返回
+    fn at_node(self, node: &ASTNode) -> Self {
+        match node.span() {
+            Some(span) => self.at(span),
+            None => self,
+        }
+    }
+
+    //错误离开位于span处的函数调用 记录位置并添加调用栈
+    fn called_at(self, name: Option<&str>, span: Span) -> Self {
+        match self {
+            Signal::Error(err) => Signal::Error(err.at(span).called_at(name, span)),
+            signal => signal,
+        }
+    }
+}
+
+impl From<RuntimeError> for Signal {
+    fn from(err: RuntimeError) -> Self {
+        Signal::Error(err)
+    }
+}
+
+impl From<ErrorKind> for Signal {
+    fn from(kind: ErrorKind) -> Self {
+        Signal::Error(kind.into())
+    }
+}
+
+//默认允许的最大函数调用深度
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+//默认假定的解释器线程栈大小 与新建线程的默认栈大小一致
+pub const DEFAULT_STACK_SIZE: usize = 2 * 1024 * 1024;
+
+thread_local! {
+    //当前(非尾调用)函数调用深度
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+    //允许的最大函数调用深度
+    static MAX_CALL_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_CALL_DEPTH) };
+    //最外层函数调用开始时的栈地址
+    static STACK_BASE: Cell<usize> = const { Cell::new(0) };
+    //函数调用最多可以使用的栈空间(字节)
+    static STACK_LIMIT: Cell<usize> = const { Cell::new(stack_limit(DEFAULT_STACK_SIZE)) };
+}
+
+//留出四分之一的栈给最外层调用之前的求值以及错误的传递
+const fn stack_limit(stack_size: usize) -> usize {
+    stack_size / 4 * 3
+}
+
+//获取允许的最大函数调用深度
+pub fn max_call_depth() -> usize {
+    MAX_CALL_DEPTH.with(|depth| depth.get())
+}
+
+//设置允许的最大函数调用深度
+pub fn set_max_call_depth(depth: usize) {
+    MAX_CALL_DEPTH.with(|max| max.set(depth));
+}
+
+//告知当前线程的栈大小 函数调用使用的栈空间超出时同样报告栈溢出
+pub fn set_stack_size(stack_size: usize) {
+    STACK_LIMIT.with(|limit| limit.set(stack_limit(stack_size)));
+}
+
+//当前栈地址 用一个局部变量的地址近似
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+//函数调用深度计数器 进入函数时加一 离开作用域时自动减一
+//超出最大深度或者栈空间即将耗尽时返回错误 避免递归过深导致解释器本身栈溢出
+pub struct CallDepthGuard;
+
+impl CallDepthGuard {
+    pub fn enter(name: Option<&str>) -> Result<Self, RuntimeError> {
+        let depth = CALL_DEPTH.with(|depth| depth.get());
+        let address = stack_address();
+        if depth == 0 {
+            STACK_BASE.with(|base| base.set(address));
+        }
+        let used = STACK_BASE.with(|base| base.get()).abs_diff(address);
+        //栈空间不足时 实际能达到的深度就是当前深度
+        let max = if used > STACK_LIMIT.with(|limit| limit.get()) { depth } else { max_call_depth() };
+        if depth >= max {
+            raise!(ErrorKind::StackOverflow { name: name.map(String::from), depth: max })
+        }
+        CALL_DEPTH.with(|d| d.set(depth + 1));
+        Ok(CallDepthGuard)
+    }
+}
+
+impl Drop for CallDepthGuard {
+    fn drop(&mut self) {
+        CALL_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+//在函数体或者顶层语句处截获控制流信号
+//尾调用在此循环执行(蹦床) return的值作为结果 游离在循环外的break/continue视为错误
+pub fn catch_return(mut result: Result<Option<ASTValue>, Signal>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError> {
+    while let Err(Signal::TailCall(fun, args, span)) = result {
+        result = fun.call_tail(&args, env.clone()).map_err(|signal| signal.called_at(fun.name(), span));
+    }
+    match result {
+        Ok(value) | Err(Signal::Return(value)) => Ok(value),
+        Err(Signal::Error(err)) => Err(err),
+        Err(Signal::Break(span)) => Err(RuntimeError::from(ErrorKind::OutsideLoop("break")).at(span)),
+        Err(Signal::Continue(span)) => Err(RuntimeError::from(ErrorKind::OutsideLoop("continue")).at(span)),
+        Err(Signal::TailCall(..)) => unreachable!(),
+    }
+}
+
+//对一句完整的顶层语句求值
+pub fn evaluate_statement(root: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError> {
+    catch_return(evaluate_node(root, env.clone()), env)
+}
+
+//对处于尾部位置的节点求值(函数体、尾部位置语句块的最后一条语句、尾部位置条件表达式的分支、return的返回值)
+//尾部位置的函数调用不会立即执行 而是以TailCall信号的形式交给catch_return
+pub fn evaluate_tail(root: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    match root {
+        ASTNode::Apply(fun, args, span) => evaluate_apply(fun, args, true, *span, env),
+        ASTNode::Cond(if_node, elseif_nodes, else_node) => evaluate_cond(if_node, elseif_nodes, else_node, true, env),
+        ASTNode::Block(nodes) => evaluate_block(nodes, true, env),
+        _ => evaluate_node(root, env),
+    }
+}
+
+//根据是否处于尾部位置选择求值方式
+fn evaluate_branch(root: &ASTNode, tail: bool, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    if tail {
+        evaluate_tail(root, env)
+    } else {
+        evaluate_node(root, env)
+    }
+}
+
 //对语法树节点求值
-pub fn evaluate_node(root: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
+pub fn evaluate_node(root: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
     match root {
         //空节点
         ASTNode::Empty => Ok(None),
@@ -15,15 +182,17 @@ pub fn evaluate_node(root: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Op
         //字面量节点
         ASTNode::Literal(val) => Ok(Some(val.clone())),
         //数值/函数变量
-        ASTNode::Var(name) => {
+        ASTNode::Var(name, span) => {
             if let Some(value) = env.borrow().get(name, false) {
                 Ok(Some(value))
             } else {
-                raise!("variable not define")
+                Err(Signal::from(ErrorKind::UndefinedName(name.clone())).at(*span))
             }
         },
         //数组元素索引
-        ASTNode::Index(arr, index) => evaluate_index(&evaluate_node(arr, env.clone())?, evaluate_node(index, env.clone())?, env),
+        ASTNode::Index(arr, index, span) => {
+            evaluate_index(&evaluate_node(arr, env.clone())?, evaluate_node(index, env.clone())?, env).map_err(|signal| signal.at(*span))
+        },
         //数组节点
         ASTNode::Array(elements) => {
             let mut results = vec![];
@@ -34,47 +203,73 @@ pub fn evaluate_node(root: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Op
             }
             Ok(Some(ASTValue::Array(results.into())))
         }
-        //单目运算表达式
-        ASTNode::Unitary(op, node) => {
-            if let Some(arg) = evaluate_node(node, env)? {
-                evaluate_unitary_op(op.clone(), arg)
-            } else {
-                raise!("Error evaluate unitary op")
+        //映射节点(重复的键以后出现的为准)
+        ASTNode::Map(entries) => {
+            let mut results = ASTValue::Map(Rc::new(vec![]));
+            for (key, node) in entries {
+                if let Some(value) = evaluate_node(node, env.clone())? {
+                    assign_index(&mut results, &[ASTValue::String(key.as_str().into())], value)?;
+                }
             }
+            Ok(Some(results))
+        }
+        //单目运算表达式
+        ASTNode::Unitary(op, node, span) => {
+            match evaluate_node(node, env)? {
+                Some(arg) => evaluate_unitary_op(*op, arg),
+                None => Err(ErrorKind::NoValue("operand").into()),
+            }.map_err(|signal| signal.at(*span))
         },
+        //逻辑运算节点(短路求值)
+        ASTNode::Binary(op @ (Op::And | Op::Or), lhs, rhs, span) => evaluate_logic_op(*op, lhs, rhs, env).map_err(|signal| signal.at(*span)),
         //双目运算节点
-        ASTNode::Binary(op, lhs, rhs) => {
-            if let (Some(lvalue), Some(rvalue)) = (evaluate_node(lhs, env.clone())?, evaluate_node(rhs, env)?) {
-                evaluate_binary_op(op.clone(), lvalue, rvalue)
-            } else {
-                raise!("Error evaluate binary op")
-            }
+        ASTNode::Binary(op, lhs, rhs, span) => {
+            match (evaluate_node(lhs, env.clone())?, evaluate_node(rhs, env)?) {
+                (Some(lvalue), Some(rvalue)) => evaluate_binary_op(*op, lvalue, rvalue),
+                _ => Err(ErrorKind::NoValue("operand").into()),
+            }.map_err(|signal| signal.at(*span))
         },
         //定义(true)赋值(false)数值/函数变量节点
-        ASTNode::Assign(name, body, define) => evaluate_assign(name, body, define.clone(), env),
+        ASTNode::Assign(target, body, define) => evaluate_assign(target, body, *define, None, env),
+        //复合赋值节点
+        ASTNode::CompoundAssign(op, target, body) => evaluate_assign(target, body, false, Some(*op), env),
         //匿名函数节点
-        ASTNode::Lambda(args, body) => evaluate_lambda(args, body, env),
+        ASTNode::Lambda(args, body) => evaluate_lambda(None, args, body, env),
         //条件表达式节点
-        ASTNode::Cond(if_node, elseif_nodes, else_node) => evaluate_cond(if_node, elseif_nodes, else_node, env),
+        ASTNode::Cond(if_node, elseif_nodes, else_node) => evaluate_cond(if_node, elseif_nodes, else_node, false, env),
+        //while循环节点
+        ASTNode::While(cond, body) => evaluate_while(cond, body, env),
+        //for循环节点
+        ASTNode::For(name, iterable, body) => evaluate_for(name, iterable, body, env),
+        //跳出循环节点
+        ASTNode::Break(span) => Err(Signal::Break(*span)),
+        //进入下一次循环节点
+        ASTNode::Continue(span) => Err(Signal::Continue(*span)),
+        //函数返回节点(返回值总是处于尾部位置)
+        ASTNode::Return(node) => Err(Signal::Return(evaluate_tail(node, env)?)),
+        //错误捕获节点
+        ASTNode::Try(body, name, handler) => evaluate_try(body, name, handler, env),
+        //抛出错误节点
+        ASTNode::Throw(node, span) => evaluate_throw(node, env).map_err(|signal| signal.at(*span)),
         //调用节点
-        ASTNode::Apply(fun, args) => evaluate_apply(fun, args, env),
+        ASTNode::Apply(fun, args, span) => evaluate_apply(fun, args, false, *span, env),
         //语句块节点
-        ASTNode::Block(nodes) => evaluate_block(nodes, env),
+        ASTNode::Block(nodes) => evaluate_block(nodes, false, env),
     }
 }
 
 //单目运算节点求值
-fn evaluate_unitary_op(op: Op, arg: ASTValue) -> Result<Option<ASTValue>, String> {
+fn evaluate_unitary_op(op: Op, arg: ASTValue) -> Result<Option<ASTValue>, Signal> {
     match (op, arg) {
         (Op::Add, ASTValue::Number(x)) => Ok(Some(ASTValue::Number(x))),
-        (Op::Sub, ASTValue::Number(x)) => Ok(Some(ASTValue::Number(-1.0 * x))),
-        (Op::Not, ASTValue::Boolean(x)) => Ok(Some(ASTValue::Boolean(!x))),
-        _ => raise!("Error evaluate unitary op"),
+        (Op::Sub, ASTValue::Number(x)) => Ok(Some(ASTValue::Number(-x))),
+        (Op::Not, x @ (ASTValue::Boolean(_) | ASTValue::Number(_))) => Ok(Some(ASTValue::Boolean(!x.boolean()?))),
+        (op, x) => raise!(ErrorKind::TypeMismatch { op: op.to_string(), types: vec![x.type_name()] }),
     }
 }
 
 //双目运算节点求值
-fn evaluate_binary_op(op: Op, lhs: ASTValue, rhs: ASTValue) -> Result<Option<ASTValue>, String> {
+fn evaluate_binary_op(op: Op, lhs: ASTValue, rhs: ASTValue) -> Result<Option<ASTValue>, Signal> {
     match (op, lhs, rhs) {
         (Op::Add, ASTValue::Number(x), ASTValue::Number(y)) => Ok(Some(ASTValue::Number(x + y))),
         (Op::Sub, ASTValue::Number(x), ASTValue::Number(y)) => Ok(Some(ASTValue::Number(x - y))),
@@ -88,22 +283,58 @@ fn evaluate_binary_op(op: Op, lhs: ASTValue, rhs: ASTValue) -> Result<Option<AST
         (Op::Gt, ASTValue::Number(x), ASTValue::Number(y)) => Ok(Some(ASTValue::Boolean(x > y))),
         (Op::Lte, ASTValue::Number(x), ASTValue::Number(y)) => Ok(Some(ASTValue::Boolean(x <= y))),
         (Op::Gte, ASTValue::Number(x), ASTValue::Number(y)) => Ok(Some(ASTValue::Boolean(x >= y))),
-        (Op::And, ASTValue::Boolean(x), ASTValue::Boolean(y)) => Ok(Some(ASTValue::Boolean(x && y))),
-        (Op::Or, ASTValue::Boolean(x), ASTValue::Boolean(y)) => Ok(Some(ASTValue::Boolean(x || y))),
-        _ => raise!("Error evaluate binary op"),
+        (Op::Eq, ASTValue::Boolean(x), ASTValue::Boolean(y)) => Ok(Some(ASTValue::Boolean(x == y))),
+        (Op::Neq, ASTValue::Boolean(x), ASTValue::Boolean(y)) => Ok(Some(ASTValue::Boolean(x != y))),
+        //字符串拼接 另一侧不是字符串时按显示格式转换为字符串
+        (Op::Add, ASTValue::String(x), y) => Ok(Some(ASTValue::String(format!("{}{}", x, y).into()))),
+        (Op::Add, x, ASTValue::String(y)) => Ok(Some(ASTValue::String(format!("{}{}", x, y).into()))),
+        //字符串按字典序比较
+        (Op::Eq, ASTValue::String(x), ASTValue::String(y)) => Ok(Some(ASTValue::Boolean(x == y))),
+        (Op::Neq, ASTValue::String(x), ASTValue::String(y)) => Ok(Some(ASTValue::Boolean(x != y))),
+        (Op::Lt, ASTValue::String(x), ASTValue::String(y)) => Ok(Some(ASTValue::Boolean(x < y))),
+        (Op::Gt, ASTValue::String(x), ASTValue::String(y)) => Ok(Some(ASTValue::Boolean(x > y))),
+        (Op::Lte, ASTValue::String(x), ASTValue::String(y)) => Ok(Some(ASTValue::Boolean(x <= y))),
+        (Op::Gte, ASTValue::String(x), ASTValue::String(y)) => Ok(Some(ASTValue::Boolean(x >= y))),
+        (op, x, y) => raise!(ErrorKind::TypeMismatch { op: op.to_string(), types: vec![x.type_name(), y.type_name()] }),
+    }
+}
+
+//逻辑运算求值
+//左侧已经能确定结果时不再计算右侧(a && b中a为假 a || b中a为真)
+//数值和布尔值一样按if条件的规则转换为真假
+fn evaluate_logic_op(op: Op, lhs: &ASTNode, rhs: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    let lvalue = match evaluate_node(lhs, env.clone())? {
+        Some(value) => value.boolean()?,
+        None => raise!(ErrorKind::NoValue("operand")),
+    };
+    if (op == Op::And && !lvalue) || (op == Op::Or && lvalue) {
+        return Ok(Some(ASTValue::Boolean(lvalue)))
+    }
+    match evaluate_node(rhs, env)? {
+        Some(value) => Ok(Some(ASTValue::Boolean(value.boolean()?))),
+        None => raise!(ErrorKind::NoValue("operand")),
     }
 }
 
 #[allow(unused)]
-fn evaluate_index(arr_node: &Option<ASTValue>, index_node: Option<ASTValue>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
+fn evaluate_index(arr_node: &Option<ASTValue>, index_node: Option<ASTValue>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
     match (arr_node, index_node) {
         //arr[i], arr = [1, 2, 3, 4]形式
         (Some(ASTValue::Array(arr)), Some(ASTValue::Number(index))) => {
-            Ok(Some(match arr.get(index.round() as usize) {
+            Ok(Some(arr[element_index(index, arr.len())?].clone()))
+        }
+        //m[key], m = {key: value}形式
+        (Some(map @ ASTValue::Map(_)), Some(ASTValue::String(key))) => {
+            Ok(Some(match map.get_key(&key) {
                 Some(v) => v.clone(),
-                _ => raise!("Error index out of bound"),
+                _ => raise!(ErrorKind::KeyNotFound(key.to_string())),
             }))
         }
+        //s[i], s = "abcd"形式 结果为单个字符构成的字符串
+        (Some(ASTValue::String(s)), Some(ASTValue::Number(index))) => {
+            let c = s.chars().nth(element_index(index, s.chars().count())?).unwrap();
+            Ok(Some(ASTValue::String(c.to_string().into())))
+        }
         //arr[i, j, ...], arr = [[1, 2, 3], [4, 5, 6], ...]形式
         (arr_node, Some(ASTValue::Array(indices))) => {
             let mut results = vec![];
@@ -114,18 +345,42 @@ fn evaluate_index(arr_node: &Option<ASTValue>, index_node: Option<ASTValue>, env
             }
             Ok(Some(ASTValue::Array(results.into())))
         },
-        _ => raise!("Error evaluate array index"),
+        (Some(arr), Some(index)) => raise!(ErrorKind::TypeMismatch { op: "[]".into(), types: vec![arr.type_name(), index.type_name()] }),
+        _ => raise!(ErrorKind::NoValue("index")),
+    }
+}
+
+//把数值下标转换为元素序号 负数、非整数以及超出长度的下标都视为越界
+fn element_index(index: f64, len: usize) -> Result<usize, RuntimeError> {
+    if index < 0.0 || index.fract() != 0.0 || index >= len as f64 {
+        raise!(ErrorKind::IndexOutOfBounds { index, len })
     }
+    Ok(index as usize)
 }
 
 //赋值表达式求值
-fn evaluate_assign(name: &str, body: &Box<ASTNode>, define: bool, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
+fn evaluate_assign(target: &ASTNode, body: &ASTNode, define: bool, op: Option<Op>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    //赋值本身的错误定位到左值
+    let (name, span) = match target {
+        ASTNode::Var(name, span) => (name, *span),
+        _ if define => return Err(Signal::from(ErrorKind::InvalidTarget("can only define a variable")).at_node(target)),
+        _ => return evaluate_index_assign(target, body, op, env),
+    };
     if define && env.borrow().get(name, true).is_some() {
-        raise!("redefine variable")
+        return Err(Signal::from(ErrorKind::Redefinition(name.clone())).at(span))
     } else if !define && env.borrow().get(name, false).is_none() {
-        raise!("undefine variable")
+        return Err(Signal::from(ErrorKind::UndefinedName(name.clone())).at(span))
     }
-    if let Some(value) = evaluate_node(body, env.clone())? {
+    //直接绑定到变量上的lambda以变量名作为函数名
+    let value = match (body, op) {
+        (ASTNode::Lambda(args, fun_body), None) => evaluate_lambda(Some(name), args, fun_body, env.clone())?,
+        (_, None) => evaluate_node(body, env.clone())?,
+        (_, Some(op)) => {
+            let current = env.borrow().get(name, false);
+            evaluate_compound(op, current, body, env.clone()).map_err(|signal| signal.at(span))?
+        },
+    };
+    if let Some(value) = value {
         if define {
             env.borrow_mut().regist(name, value.clone());
         } else {
@@ -133,210 +388,287 @@ fn evaluate_assign(name: &str, body: &Box<ASTNode>, define: bool, env: Rc<RefCel
         }
         Ok(Some(value))
     } else {
-        raise!("can not assign variable with ()")
+        Err(Signal::from(ErrorKind::NoValue("assigned expression")).at(span))
+    }
+}
+
+//索引赋值表达式求值 arr[i] = v, m[i][j] = v
+//数组在多处共享时先复制再修改(copy-on-write) 保证其他别名看到的值不变
+fn evaluate_index_assign(target: &ASTNode, body: &ASTNode, op: Option<Op>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    let mut indices = vec![];
+    let (name, root) = evaluate_lvalue(target, &mut indices, env.clone())?;
+    if env.borrow().get(name, false).is_none() {
+        return Err(Signal::from(ErrorKind::UndefinedName(name.to_string())).at(root))
+    }
+    let value = match op {
+        None => evaluate_node(body, env.clone())?,
+        Some(op) => {
+            //下标只求值一次 沿下标链取出元素原有的值
+            let mut current = env.borrow().get(name, false);
+            for index in &indices {
+                current = evaluate_index(&current, Some(index.clone()), env.clone()).map_err(|signal| signal.at_node(target))?;
+            }
+            evaluate_compound(op, current, body, env.clone()).map_err(|signal| signal.at_node(target))?
+        },
+    };
+    if let Some(value) = value {
+        env.borrow_mut().modify(name, |root| assign_index(root, &indices, value.clone())).unwrap()
+            .map_err(|err| Signal::from(err).at_node(target))?;
+        Ok(Some(value))
+    } else {
+        Err(Signal::from(ErrorKind::NoValue("assigned expression")).at_node(target))
+    }
+}
+
+//复合赋值求值 左值原有的值与右侧表达式的值做双目运算
+fn evaluate_compound(op: Op, current: Option<ASTValue>, body: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    match (current, evaluate_node(body, env)?) {
+        (Some(lvalue), Some(rvalue)) => evaluate_binary_op(op, lvalue, rvalue),
+        _ => raise!(ErrorKind::NoValue("assigned expression")),
+    }
+}
+
+//从左值索引链中依次求出各级下标(数组的序号或者映射的键) 返回根变量名及其位置
+fn evaluate_lvalue<'a>(target: &'a ASTNode, indices: &mut Vec<ASTValue>, env: Rc<RefCell<Environment>>) -> Result<(&'a str, Span), Signal> {
+    match target {
+        ASTNode::Var(name, span) => Ok((name, *span)),
+        ASTNode::Index(arr, index, span) => {
+            let root = evaluate_lvalue(arr, indices, env.clone())?;
+            match evaluate_node(index, env)? {
+                Some(index) => indices.push(index),
+                _ => return Err(Signal::from(ErrorKind::NoValue("index")).at(*span)),
+            }
+            Ok(root)
+        },
+        _ => Err(Signal::from(ErrorKind::InvalidTarget("can not assign to expression")).at_node(target)),
+    }
+}
+
+//按下标链修改数组或者映射中的元素 映射中不存在的键会被添加到末尾
+fn assign_index(container: &mut ASTValue, indices: &[ASTValue], value: ASTValue) -> Result<(), RuntimeError> {
+    match (container, indices.split_first()) {
+        (container, None) => *container = value,
+        (ASTValue::Array(arr), Some((ASTValue::Number(index), rest))) => {
+            let i = element_index(*index, arr.len())?;
+            //Rc只被引用一次时原地修改 否则复制一份新的数组
+            assign_index(&mut Rc::make_mut(arr)[i], rest, value)?
+        },
+        (ASTValue::Map(entries), Some((ASTValue::String(key), rest))) => {
+            //与数组相同 共享时先复制再修改
+            let entries = Rc::make_mut(entries);
+            match entries.iter().position(|(k, _)| **k == **key) {
+                Some(i) => assign_index(&mut entries[i].1, rest, value)?,
+                None if rest.is_empty() => entries.push((key.to_string(), value)),
+                None => raise!(ErrorKind::KeyNotFound(key.to_string())),
+            }
+        },
+        (container, Some((index, _))) => {
+            raise!(ErrorKind::TypeMismatch { op: "[]".into(), types: vec![container.type_name(), index.type_name()] })
+        },
     }
+    Ok(())
 }
 
 //条件表达式求值
-fn evaluate_cond(if_node: &Box<(ASTNode, ASTNode)>, 
+fn evaluate_cond(if_node: &(ASTNode, ASTNode), 
                           elseif_nodes: &Vec<(ASTNode, ASTNode)>, 
                           else_node: &Option<Box<ASTNode>>, 
-                          env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
+                          tail: bool,
+                          env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
     if let Some(flag1) = evaluate_node(&if_node.0, env.clone())? {
         //分支
         if flag1.boolean()? {
-            evaluate_node(&if_node.1, env.clone())
+            evaluate_branch(&if_node.1, tail, env.clone())
         } else {
             //else if 分支
             for (cond, branch) in elseif_nodes {
                 if let Some(flag2) = evaluate_node(cond, env.clone())? {
                     if flag2.boolean()? {
                         //命中某个elseif分支直接返回
-                        return evaluate_node(branch, env)
+                        return evaluate_branch(branch, tail, env)
                     } 
                 }
             }
             //else 分支
             if else_node.is_some() {
-                return evaluate_node(else_node.as_ref().unwrap(), env)
+                return evaluate_branch(else_node.as_ref().unwrap(), tail, env)
             }
             //没有else分支 或者 elseif所有分支都没有命中
             Ok(None)
         }
     } else {
-        raise!("evaluate condition failed")
+        raise!(ErrorKind::NoValue("condition"))
     }
 }
 
-//匿名函数求值 (目前这个函数的实现存在问题 高阶lambda调用存在问题 以后会改进)
-#[allow(unused)]
-fn evaluate_lambda(args: &Vec<String>, body: &Box<ASTNode>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
-    /*Ok(Some(ASTValue::Function(Rc::new(UsrDefFun {
-        name: None,
-        params: args.to_owned(),
-        body: Rc::new(*body.clone()),
-    }))))*/
+//while循环求值(循环体的值被丢弃 整个循环语句无返回值)
+fn evaluate_while(cond: &ASTNode, body: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    loop {
+        match evaluate_node(cond, env.clone())? {
+            Some(flag) => {
+                if !flag.boolean()? {
+                    break;
+                }
+            },
+            None => raise!(ErrorKind::NoValue("condition")),
+        }
+        if !evaluate_loop_body(body, env.clone())? {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+//for循环求值 每次迭代都在新的子环境中绑定循环变量
+fn evaluate_for(name: &str, iterable: &ASTNode, body: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    let elements = match evaluate_node(iterable, env.clone())? {
+        Some(ASTValue::Array(elements)) => elements,
+        //映射按插入顺序迭代键
+        Some(ASTValue::Map(entries)) => entries.iter().map(|(key, _)| ASTValue::String(key.as_str().into())).collect(),
+        //字符串逐个字符迭代
+        Some(ASTValue::String(s)) => s.chars().map(|c| ASTValue::String(c.to_string().into())).collect(),
+        Some(value) => raise!(ErrorKind::WrongType { expected: "array, map or string", found: value.type_name() }),
+        None => raise!(ErrorKind::NoValue("iterable")),
+    };
+    for element in elements.iter() {
+        let sub_env = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
+        sub_env.borrow_mut().regist(name, element.clone());
+        if !evaluate_loop_body(body, sub_env)? {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+//执行一次循环体 返回false表示遇到了break需要结束循环
+fn evaluate_loop_body(body: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<bool, Signal> {
+    match evaluate_node(body, env) {
+        Ok(_) | Err(Signal::Continue(_)) => Ok(true),
+        Err(Signal::Break(_)) => Ok(false),
+        Err(signal) => Err(signal),
+    }
+}
+
+//错误捕获求值
+//try语句块中的求值错误被截获 以{message, kind}映射的形式绑定到catch的变量上 然后执行catch语句块
+//break、continue、return等其余信号照常向外传递
+fn evaluate_try(body: &ASTNode, name: &str, handler: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    let err = match evaluate_node(body, env.clone()) {
+        //return f(x)中的尾调用要在try之内执行 否则其中的错误不会被捕获
+        Err(Signal::TailCall(fun, args, span)) => match fun.call(&args, env.clone()) {
+            Ok(value) => return Err(Signal::Return(value)),
+            Err(err) => err.at(span).called_at(fun.name(), span),
+        },
+        Err(Signal::Error(err)) => err,
+        result => return result,
+    };
+    let sub_env = Rc::new(RefCell::new(Environment::new(Some(env))));
+    sub_env.borrow_mut().regist(name, ASTValue::Map(Rc::new(vec![
+        ("message".to_string(), ASTValue::String(err.to_string().into())),
+        ("kind".to_string(), ASTValue::String(err.kind.name().into())),
+    ])));
+    evaluate_node(handler, sub_env)
+}
 
+//抛出错误求值
+//字符串作为错误信息 种类为error
+//catch得到的映射按其中的message和kind重新抛出
+fn evaluate_throw(node: &ASTNode, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    let (kind, message) = match evaluate_node(node, env)? {
+        Some(ASTValue::String(message)) => ("error".into(), message),
+        Some(value @ ASTValue::Map(_)) => match (value.get_key("kind"), value.get_key("message")) {
+            (Some(ASTValue::String(kind)), Some(ASTValue::String(message))) => (kind.to_string(), message.clone()),
+            (None, Some(ASTValue::String(message))) => ("error".into(), message.clone()),
+            (_, None) => raise!(ErrorKind::KeyNotFound("message".into())),
+            //kind或者message不是字符串
+            (Some(kind), Some(ASTValue::String(_))) => raise!(ErrorKind::WrongType { expected: "string", found: kind.type_name() }),
+            (_, Some(message)) => raise!(ErrorKind::WrongType { expected: "string", found: message.type_name() }),
+        },
+        Some(value) => raise!(ErrorKind::WrongType { expected: "string or map", found: value.type_name() }),
+        None => raise!(ErrorKind::NoValue("thrown expression")),
+    };
+    raise!(ErrorKind::User { kind, message: message.to_string() })
+}
+
+//匿名函数求值
+//lambda持有定义时所在的环境 调用时函数体在该环境的子环境中执行(词法闭包)
+//函数体通过该环境查找自身名字 因此递归和同一作用域内的相互递归都可以正常工作
+fn evaluate_lambda(name: Option<&str>, args: &[String], body: &Rc<ASTNode>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
     Ok(Some(ASTValue::Function(Rc::new(UsrDefFun {
-        name: None,
+        name: name.map(String::from),
         params: args.to_owned(),
-        body: Rc::new(capture_outside_variable(body, args, env)?),
+        body: body.clone(),
+        env,
     }))))
 }
 
 //调用节点求值
 //支持[(x) => {x + 2}, (x) => {x^2 + 2}](2)形式的调用
-fn evaluate_apply(fun_node: &ASTNode, arg_nodes: &Vec<ASTNode>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
+//tail为true时单一函数的调用以TailCall信号返回
+//调用过程中尚未定位的错误(实参数量不符、原始函数内部的错误等)定位到整个调用表达式
+fn evaluate_apply(fun_node: &ASTNode, arg_nodes: &[ASTNode], tail: bool, span: Span, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
     match evaluate_node(fun_node, env.clone())? {
         //单一函数
         Some(ASTValue::Function(fun)) => {
-            //计算各个实参
-            let mut args = vec![];
-            for arg_node in arg_nodes {
-                if let Some(result) = evaluate_node(arg_node, env.clone())? {
-                    args.push(result);
-                } else {
-                    //语法错误
-                    raise!("undefine behavior")
-                }
+            let args = evaluate_args(arg_nodes, env.clone()).map_err(|signal| signal.at(span))?;
+            if tail {
+                Err(Signal::TailCall(fun, args, span))
+            } else {
+                Ok(fun.call(&args, env).map_err(|err| err.at(span).called_at(fun.name(), span))?)
             }
-            fun.call(&args, env)
         },
         //多个函数放在一个队列中
-        //这边的逻辑需要优化
         Some(ASTValue::Array(fun_nodes)) => {
+            let args = evaluate_args(arg_nodes, env.clone()).map_err(|signal| signal.at(span))?;
             let mut results = vec![];
             for fun_node in fun_nodes.iter() {
                 if let ASTValue::Function(ref fun) = fun_node {
-                    //计算各个实参
-                    let mut args = vec![];
-                    for arg_node in arg_nodes {
-                        if let Some(result) = evaluate_node(arg_node, env.clone())? {
-                            args.push(result);
-                        } else {
-                            //语法错误
-                            raise!("undefine behavior")
-                        }
-                    }
-                    if let Some(result) = fun.call(&args, env.clone())? {
+                    if let Some(result) = fun.call(&args, env.clone()).map_err(|err| err.at(span).called_at(fun.name(), span))? {
                         results.push(result)
                     }
                 }
             }
             Ok(Some(ASTValue::Array(results.into())))
         }
-        _ => raise!("evaluate apply failed"),
+        Some(value) => Err(Signal::from(ErrorKind::NotCallable(value.type_name())).at(span)),
+        None => Err(Signal::from(ErrorKind::NoValue("function")).at(span)),
     }
 }
 
-fn evaluate_block(nodes: &Vec<ASTNode>, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
-    //创建子环境
-    let sub_env = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
-    for node in nodes {
-        //遇到有值返回的则直接返回
-        if let Some(result) = evaluate_node(node, sub_env.clone())? {
-            return Ok(Some(result))
+//计算各个实参
+fn evaluate_args(arg_nodes: &[ASTNode], env: Rc<RefCell<Environment>>) -> Result<Vec<ASTValue>, Signal> {
+    let mut args = vec![];
+    for arg_node in arg_nodes {
+        if let Some(result) = evaluate_node(arg_node, env.clone())? {
+            args.push(result);
+        } else {
+            raise!(ErrorKind::NoValue("argument"))
         }
     }
-    Ok(None)
+    Ok(args)
 }
 
-//此函数的应用于以下场景
-//let f = (z) => {(x, y) => (x + y + z)}
-//当执行f(3)时返回的lambda中的z应该替换为z
-//这种机制的存在意味着lambda表达式中包含的外部变量不能出现在赋值符号左侧
-//针对上面的案例 这意味着lambda的实体内不能出现z = xxx或者let z = xxx的语句
-fn capture_outside_variable(lambda: &ASTNode, bound: &[String], env: Rc<RefCell<Environment>>) -> Result<ASTNode, String> {
-    Ok(match lambda {
-        //空节点
-        ASTNode::Empty => ASTNode::Empty,
-        //无返回值节点(将返回值设置为None 实现忽略)
-        ASTNode::Void(node) => ASTNode::Void(Box::new(capture_outside_variable(node, bound, env)?)),
-        //字面量节点
-        ASTNode::Literal(val) => ASTNode::Literal(val.clone()),
-        //数值/函数变量
-        ASTNode::Var(name) => {
-            if let Some(val) = env.borrow().get(name, false) {
-                ASTNode::Literal(val.clone())
-            } else {
-                //有些环境表找不到的变量是生命在lambda内部的 运行的时候再做判断即可
-                ASTNode::Var(name.clone())
-            }
-        },
-        //数组元素索引
-        ASTNode::Index(arr, index) => {
-            let new_arr = Box::new(capture_outside_variable(arr, bound, env.clone())?);
-            let new_index = Box::new(capture_outside_variable(index, bound, env.clone())?);
-            ASTNode::Index(new_arr, new_index)
-        }
-        //数组节点
-        ASTNode::Array(elements) => {
-            let mut new_elements = vec![];
-            for element in elements {
-                new_elements.push(capture_outside_variable(element, bound, env.clone())?)
-            }
-            ASTNode::Array(new_elements)
-        }
-        //单目运算表达式
-        ASTNode::Unitary(op, node) => ASTNode::Unitary(*op, Box::new(capture_outside_variable(node, bound, env)?)),
-        //双目运算节点
-        ASTNode::Binary(op, lhs, rhs) => {
-            let new_lhs = Box::new(capture_outside_variable(lhs, bound, env.clone())?);
-            let new_rhs = Box::new(capture_outside_variable(rhs, bound, env)?);
-            ASTNode::Binary(*op, new_lhs, new_rhs)
-        },
-        //定义(true)赋值(false)数值/函数变量节点
-        ASTNode::Assign(name, body, define) => {
-            if env.borrow().get(name, false).is_none() {
-                ASTNode::Assign(name.clone(), body.clone(), *define)
-            } else {
-                raise!("capture variable are const, can't re-assign/re-definition")
-            }
-        },
-        //匿名函数节点
-        ASTNode::Lambda(args, body) => {
-            let mut new_bound = vec![];
-            for arg in args {
-                new_bound.push(arg.clone());
-            }
-            for arg in bound {
-                new_bound.push(arg.clone());
+//语句块求值 tail为true时最后一条语句处于尾部位置
+fn evaluate_block(nodes: &[ASTNode], tail: bool, env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
+    //创建子环境
+    let sub_env = Rc::new(RefCell::new(Environment::new(Some(env.clone()))));
+    let result = (|| {
+        for (i, node) in nodes.iter().enumerate() {
+            //遇到有值返回的则直接返回
+            if let Some(result) = evaluate_branch(node, tail && i + 1 == nodes.len(), sub_env.clone())? {
+                return Ok(Some(result))
             }
-            ASTNode::Lambda(args.clone(), Box::new(capture_outside_variable(body, &new_bound[..], env.clone())?))
         }
-        //条件表达式节点
-        ASTNode::Cond(if_node, elseif_nodes, else_node) => {
-            let new_if_node = Box::new((capture_outside_variable(&if_node.0, bound, env.clone())?, capture_outside_variable(&if_node.1, bound, env.clone())?));
-            let mut new_elseif_nodes = vec![];
-            for elseif_node in elseif_nodes {
-                let new_elseif_node = (capture_outside_variable(&elseif_node.0, bound, env.clone())?, capture_outside_variable(&elseif_node.1, bound, env.clone())?);
-                new_elseif_nodes.push(new_elseif_node);
-            }
-            let new_else_node = if else_node.is_some() {
-                Some(Box::new(capture_outside_variable(&else_node.as_ref().unwrap(), bound, env)?))
-            } else {
-                None
-            };
-            ASTNode::Cond(new_if_node, new_elseif_nodes, new_else_node)
-        },
-        //调用节点
-        ASTNode::Apply(fun, args) => {
-            //由于存在递归调用的可能 只能把函数体本身的外部绑定这种行为给禁止掉
-            //这个问题还是有希望解决的 只要在函数定义部分绑定函数时bound多加上函数名字本身即可以后再解决
-            //let new_fun = Box::new(capture_outside_variable(fun, bound, env.clone())?);
-            let mut new_args = vec![];
-            for arg in args {
-                new_args.push(capture_outside_variable(arg, bound, env.clone())?)
-            }
-            ASTNode::Apply(fun.clone(), new_args)
+        Ok(None)
+    })();
+    let result = match result {
+        //尾调用语句块中定义的函数时在此处执行 执行完之后才能释放语句块的环境
+        Err(Signal::TailCall(fun, args, span)) if fun.env().is_some_and(|captured| Rc::ptr_eq(captured, &sub_env)) => match fun.call(&args, env) {
+            Ok(value) => Err(Signal::Return(value)),
+            Err(err) => Err(Signal::Error(err.at(span).called_at(fun.name(), span))),
         },
-        //语句块节点
-        ASTNode::Block(nodes) => {
-            let mut new_nodes = vec![];
-            for node in nodes {
-                new_nodes.push(capture_outside_variable(node, bound, env.clone())?)
-            }
-            ASTNode::Block(new_nodes)
-        }
-    })
-}
\ No newline at end of file
+        result => result,
+    };
+    Environment::release(sub_env);
+    result
+}
@@ -1,6 +1,6 @@
 use std::str::Chars;
 use std::iter::{Fuse, Peekable};
-use crate::gramma::token::{Token, Op};
+use crate::gramma::token::{Token, Op, TemplatePart};
 
 //词法分析器
 pub struct Lexer {
@@ -8,6 +8,10 @@ pub struct Lexer {
     tokens: Vec<Token>,
     //可以用来指示错误发生的位置
     spans: Vec<Span>,
+    //符号与前一个符号之间是否有换行
+    breaks: Vec<bool>,
+    //输入在整个源码中的起始位置 没有任何符号时用它定位
+    offset: usize,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -15,9 +19,18 @@ pub struct Span(pub usize, pub usize);
 
 impl Lexer {
     pub fn new(line: &str) -> Lexer {
+        Self::with_offset(line, 0)
+    }
+
+    //line是更大的源码中从第offset个字符开始的一段 符号位置按整个源码计算
+    pub fn with_offset(line: &str, offset: usize) -> Lexer {
         let mut stream = CharStream::new(line);
+        stream.icurrent = offset;
         let mut tokens = vec![];
         let mut spans = vec![];
+        let mut breaks = vec![];
+        //上一个符号之后是否出现过换行
+        let mut newline = false;
 
         //把line中的字符转化为token存储在向量tokens中
         loop {
@@ -27,19 +40,105 @@ impl Lexer {
                 break;
             } else if c.is_whitespace() {
                 //空格则继续loop
-                stream.next();
+                if stream.next() == '\n' {
+                    newline = true;
+                }
                 continue;
+            } else if c == '/' && stream.peek_second() == '/' {
+                //行注释 跳过直到换行(换行本身作为空白字符处理)
+                while !matches!(stream.peek(), '\n' | '\0') {
+                    stream.next();
+                }
+            } else if c == '/' && stream.peek_second() == '*' {
+                //块注释 跳过直到*/ 没有闭合时整个注释作为非法符号
+                let begin = stream.icurrent;
+                stream.next();
+                stream.next();
+                loop {
+                    match stream.next() {
+                        '*' if stream.peek() == '/' => {
+                            stream.next();
+                            break;
+                        },
+                        '\0' => {
+                            tokens.push(Token::Illegal('/'));
+                            spans.push(Span(begin, stream.icurrent - 1));
+                            break;
+                        },
+                        '\n' => newline = true,
+                        _ => (),
+                    }
+                }
+            } else if c.is_ascii_digit() || (c == '.' && stream.peek_second().is_ascii_digit()) {
+                //数字 格式错误时只把出错的那个字符标记为非法符号
+                let begin = stream.icurrent;
+                match Self::parse_number(&mut stream) {
+                    Ok(token) => {
+                        tokens.push(token);
+                        spans.push(Span(begin, stream.icurrent));
+                    },
+                    Err((c, offset)) => {
+                        tokens.push(Token::Illegal(c));
+                        spans.push(Span(begin + offset, begin + offset + 1));
+                    },
+                }
+            } else if c == '"' {
+                //字符串 转义字符错误或者没有闭合时只标注出错的位置
+                let begin = stream.icurrent;
+                match Self::parse_string(&mut stream) {
+                    Ok(token) => {
+                        tokens.push(token);
+                        spans.push(Span(begin, stream.icurrent));
+                    },
+                    Err((c, span)) => {
+                        tokens.push(Token::Illegal(c));
+                        spans.push(span);
+                    },
+                }
             } else {
                 let begin = stream.icurrent;
                 tokens.push(Self::parse_token(&mut stream));
                 spans.push(Span(begin, stream.icurrent));
             }
+            //本轮产生了新的符号时记录它之前是否有换行
+            if breaks.len() < spans.len() {
+                breaks.push(newline);
+                newline = false;
+            }
         }
+        Self::merge_increments(&mut tokens, &mut spans, &mut breaks);
 
         Lexer {
             icurrent: 0,
             tokens,
             spans,
+            breaks,
+            offset,
+        }
+    }
+
+    //紧挨着的++、--位于语句结尾(;、}、换行或者输入结束之前)时是自增、自减
+    //紧跟在变量或者]之后且后面是运算符时同样是自增、自减(由解析器报错 例如x++ + 1) 其余情况仍是两个运算符(例如1--2、a--b)
+    fn merge_increments(tokens: &mut Vec<Token>, spans: &mut Vec<Span>, breaks: &mut Vec<bool>) {
+        let mut i = 0;
+        while i + 1 < tokens.len() {
+            let adjacent = spans[i].1 == spans[i + 1].0;
+            let at_end = match tokens.get(i + 2) {
+                None | Some(Token::SemiColon) | Some(Token::RightBrace) => true,
+                Some(_) => breaks[i + 2],
+            };
+            let postfix = i > 0 && spans[i - 1].1 == spans[i].0
+                && matches!(tokens[i - 1], Token::Symbol(_) | Token::RightBracket)
+                && matches!(tokens.get(i + 2), Some(Token::Operator(_)));
+            match (&tokens[i], &tokens[i + 1]) {
+                (Token::Operator(op @ (Op::Add | Op::Sub)), Token::Operator(next)) if op == next && adjacent && (at_end || postfix) => {
+                    tokens.splice(i..i + 2, [Token::Increment(*op)]);
+                    spans.splice(i..i + 2, [Span(spans[i].0, spans[i + 1].1)]);
+                    breaks.remove(i + 1);
+                },
+                _ => (),
+            }
+            i += 1;
         }
     }
 
@@ -62,16 +161,50 @@ impl Lexer {
         if self.icurrent < self.tokens.len() {
             self.spans[self.icurrent]
         } else {
-            *self.spans.last().unwrap()
+            self.spans.last().cloned().unwrap_or(Span(self.offset, self.offset))
+        }
+    }
+
+    //当前读到第几个符号 与seek配合用于回退到之前的位置
+    pub fn position(&self) -> usize {
+        self.icurrent
+    }
+
+    pub fn seek(&mut self, position: usize) {
+        self.icurrent = position;
+    }
+
+    //刚刚读过的符号的位置
+    pub fn prev_span(&self) -> Span {
+        match self.icurrent.checked_sub(1).and_then(|i| self.spans.get(i)) {
+            Some(span) => *span,
+            None => self.span(),
         }
     }
+
+    //当前符号是否位于语句结尾之后(;、}、换行或者输入结束)
+    pub fn ends_statement(&self) -> bool {
+        match self.peek() {
+            Token::SemiColon | Token::RightBrace | Token::End => true,
+            _ => self.breaks[self.icurrent],
+        }
+    }
+
+    //是否存在尚未闭合的括号(REPL据此决定是否继续读入下一行)
+    pub fn unclosed(&self) -> bool {
+        let depth = self.tokens.iter().fold(0, |depth, tk| match tk {
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth + 1,
+            Token::RightParen | Token::RightBracket | Token::RightBrace => depth - 1,
+            _ => depth,
+        });
+        depth > 0
+    }
 }
 
 impl Lexer {
     fn parse_token(stream: &mut CharStream) -> Token {
-        const DIGITS: &'static str = "0123456789";
-        const DIGITS_DOT: &'static str = "0123456789.";
-        const SYMBOLS: &'static str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_";
+        const DIGITS: &str = "0123456789";
+        const SYMBOLS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz_";
 
         //这里mut的原因是下面会多次改变该值
         let mut c = stream.peek();
@@ -86,24 +219,24 @@ impl Lexer {
             }
 
             //关键词
-            return match buffer.as_ref() {
+            match buffer.as_ref() {
                 "let" => Token::Let,
                 "if" => Token::If,
                 "elseif" => Token::ElseIf,
                 "else" => Token::Else,
+                "while" => Token::While,
+                "for" => Token::For,
+                "in" => Token::In,
+                "break" => Token::Break,
+                "continue" => Token::Continue,
+                "return" => Token::Return,
+                "try" => Token::Try,
+                "catch" => Token::Catch,
+                "throw" => Token::Throw,
                 "true" => Token::Boolean(true),
                 "false" => Token::Boolean(false),
                 _ => Token::Symbol(buffer),
-            };
-        } else if DIGITS_DOT.contains(c) {
-            //数字
-            let mut buffer = String::new();
-            while DIGITS_DOT.contains(c) {
-                buffer.push(c);
-                stream.next();
-                c = stream.peek();
             }
-            return Token::Number(buffer)
         } else {
             //其他符号
             stream.next();
@@ -116,13 +249,19 @@ impl Lexer {
                 ('&', '&') => Some(Token::Operator(Op::And)),
                 ('|', '|') => Some(Token::Operator(Op::Or)),
                 ('=', '>') => Some(Token::Arrow),
+                ('+', '=') => Some(Token::CompoundAssign(Op::Add)),
+                ('-', '=') => Some(Token::CompoundAssign(Op::Sub)),
+                ('*', '=') => Some(Token::CompoundAssign(Op::Mul)),
+                ('/', '=') => Some(Token::CompoundAssign(Op::Div)),
+                ('^', '=') => Some(Token::CompoundAssign(Op::Pow)),
+                ('%', '=') => Some(Token::CompoundAssign(Op::Mod)),
                 _ => None,
             };
 
-            if tk.is_some() {
+            if let Some(tk) = tk {
                 //由两个符号构成的运算符
                 stream.next();
-                tk.unwrap()
+                tk
             } else {
                 //由单个符号构成的运算符
                 match c {
@@ -135,6 +274,7 @@ impl Lexer {
                     ',' => Token::Comma,
                     '=' => Token::Assign,
                     ':' => Token::Colon,
+                    '.' => Token::Dot,
                     ';' => Token::SemiColon,
                     '+' => Token::Operator(Op::Add),
                     '-' => Token::Operator(Op::Sub),
@@ -152,6 +292,167 @@ impl Lexer {
     }
 }
 
+impl Lexer {
+    //解析数字字面量 支持以下格式
+    //十进制:12、1.5、.5、1_000_000、1e-6、6.02E23
+    //十六进制:0xFF 二进制:0b1010 (同样可以用_分隔)
+    //返回的Number中是可以直接用parse解析的十进制文本
+    //格式错误时返回出错的字符及其相对数字开头的位置
+    fn parse_number(stream: &mut CharStream) -> Result<Token, (char, usize)> {
+        //先贪婪地读取整个字面量 再校验格式
+        let mut chars: Vec<char> = vec![];
+        let radix = match (stream.peek(), stream.peek_second()) {
+            ('0', 'x') | ('0', 'X') => 16,
+            ('0', 'b') | ('0', 'B') => 2,
+            _ => 10,
+        };
+        loop {
+            let c = stream.peek();
+            //十进制指数部分可以带符号
+            let sign = radix == 10 && (c == '+' || c == '-') && matches!(chars.last(), Some('e') | Some('E'));
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' || sign {
+                chars.push(c);
+                stream.next();
+            } else {
+                break;
+            }
+        }
+
+        if radix != 10 {
+            let mut value = 0.0;
+            let mut has_digit = false;
+            for (i, &c) in chars.iter().enumerate().skip(2) {
+                match c.to_digit(radix) {
+                    Some(d) => {
+                        value = value * radix as f64 + d as f64;
+                        has_digit = true;
+                    },
+                    //分隔符只能位于两个数字之间 不能紧跟在0x、0b之后
+                    None if c == '_' && chars[i - 1].is_digit(radix) && chars.get(i + 1).is_some_and(|next| next.is_digit(radix)) => (),
+                    None => return Err((c, i)),
+                }
+            }
+            if !has_digit {
+                return Err((chars[1], 1));
+            }
+            return Ok(Token::Number(value.to_string()));
+        }
+
+        let mut has_dot = false;
+        let mut has_exp = false;
+        let mut has_digit = false;
+        let mut has_exp_digit = false;
+        for (i, &c) in chars.iter().enumerate() {
+            let prev = if i > 0 { chars[i - 1] } else { '\0' };
+            match c {
+                '0'..='9' if has_exp => has_exp_digit = true,
+                '0'..='9' => has_digit = true,
+                //分隔符只能位于两个数字之间
+                '_' if prev.is_ascii_digit() && chars.get(i + 1).is_some_and(char::is_ascii_digit) => (),
+                '.' if !has_dot && !has_exp => has_dot = true,
+                'e' | 'E' if has_digit && !has_exp => has_exp = true,
+                '+' | '-' => (),
+                _ => return Err((c, i)),
+            }
+        }
+        if has_exp && !has_exp_digit {
+            //指数部分没有数字
+            let i = chars.len() - 1;
+            return Err((chars[i], i));
+        }
+        Ok(Token::Number(chars.into_iter().filter(|&c| c != '_').collect()))
+    }
+
+    //解析字符串字面量 支持\n \t \r \0 \\ \" \' \{ \}转义
+    //字符串中的{expr}为插值表达式 含有插值时返回Template
+    //字符串没有闭合或者存在非法转义时返回Illegal
+    //出错时返回非法字符及其位置
+    fn parse_string(stream: &mut CharStream) -> Result<Token, (char, Span)> {
+        //跳过开头的引号
+        let begin = stream.icurrent;
+        stream.next();
+        let mut parts = vec![];
+        let mut buffer = String::new();
+        loop {
+            match stream.next() {
+                '"' => break,
+                '{' => {
+                    //插值表达式没有闭合时标注{
+                    let offset = stream.icurrent;
+                    match Self::parse_template_expr(stream) {
+                        Some(expr) => {
+                            if !buffer.is_empty() {
+                                parts.push(TemplatePart::Text(std::mem::take(&mut buffer)));
+                            }
+                            parts.push(TemplatePart::Expr(expr, offset));
+                        },
+                        None => return Err(('{', Span(offset - 1, offset))),
+                    }
+                },
+                '\\' => {
+                    //标注整个\x
+                    let escape = stream.icurrent - 1;
+                    let c = match stream.next() {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        '\\' => '\\',
+                        '"' => '"',
+                        '\'' => '\'',
+                        '{' => '{',
+                        '}' => '}',
+                        '\0' => return Err(('"', Span(begin, begin + 1))),
+                        c => return Err((c, Span(escape, stream.icurrent))),
+                    };
+                    buffer.push(c);
+                },
+                //输入结束时字符串仍未闭合 标注开头的引号
+                '\0' => return Err(('"', Span(begin, begin + 1))),
+                c => buffer.push(c),
+            }
+        }
+
+        if parts.is_empty() {
+            Ok(Token::String(buffer))
+        } else {
+            if !buffer.is_empty() {
+                parts.push(TemplatePart::Text(buffer));
+            }
+            Ok(Token::Template(parts))
+        }
+    }
+
+    //读取插值表达式的源码直到与之匹配的} 表达式中可以嵌套{}和字符串
+    //输入结束时仍未匹配则返回None
+    fn parse_template_expr(stream: &mut CharStream) -> Option<String> {
+        let mut expr = String::new();
+        let mut depth = 0;
+        let mut in_string = false;
+        loop {
+            let c = stream.next();
+            match c {
+                '\0' => return None,
+                '"' => in_string = !in_string,
+                '\\' if in_string => {
+                    expr.push(c);
+                    expr.push(stream.next());
+                    continue;
+                },
+                '{' if !in_string => depth += 1,
+                '}' if !in_string => {
+                    if depth == 0 {
+                        return Some(expr);
+                    }
+                    depth -= 1;
+                },
+                _ => (),
+            }
+            expr.push(c);
+        }
+    }
+}
+
 struct CharStream<'a> {
     icurrent: usize,
     iterator: Peekable<Fuse<Chars<'a>>>,
@@ -175,6 +476,13 @@ impl<'a> CharStream<'a> {
     fn peek(&mut self) -> char {
         self.iterator.peek().cloned().unwrap_or('\0')
     }
+
+    //查看顶部元素之后的第二个元素
+    fn peek_second(&self) -> char {
+        let mut iterator = self.iterator.clone();
+        iterator.next();
+        iterator.next().unwrap_or('\0')
+    }
 }
 
 #[cfg(test)]
@@ -195,4 +503,15 @@ mod test_charstream {
         assert_eq!(stream.peek(), '\0');
         assert_eq!(stream.next(), '\0');
     }
+
+    #[test]
+    fn input2() {
+        let line = "ab";
+        let mut stream = CharStream::new(line);
+
+        assert_eq!(stream.peek_second(), 'b');
+        assert_eq!(stream.next(), 'a');
+        assert_eq!(stream.peek_second(), '\0');
+        assert_eq!(stream.peek(), 'b');
+    }
 }
\ No newline at end of file
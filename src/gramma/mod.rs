@@ -3,6 +3,8 @@ mod util;
 pub mod token;
 pub mod ast;
 pub mod lexer;
+pub mod diagnostic;
+pub mod error;
 pub mod parser;
 pub mod environment;
 mod usrfun;
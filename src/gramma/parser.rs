@@ -1,17 +1,138 @@
-use crate::gramma::token::{Token, Op};
+use std::fmt;
+use std::rc::Rc;
+use crate::gramma::token::{Token, Op, Assoc, TemplatePart};
 use crate::gramma::lexer::{Lexer, Span};
 use crate::gramma::ast::{ASTNode, ASTValue};
+use crate::gramma::diagnostic::annotate;
 
 //解析错误定位信息
 #[derive(Debug)]
 pub struct ParseError {
     pub token: Token,
     pub span: Span,
+    //期望出现的内容 为空时只提示出现了不该出现的符号
+    pub expected: Vec<String>,
 }
 
-//解析完整的一句语句(包括结尾的;)
+impl ParseError {
+    //带有源码行和^^^标注的完整错误信息
+    pub fn render(&self, source: &str) -> String {
+        //输入提前结束时标注在最后一个符号之后
+        let span = match self.token {
+            Token::End => Span(self.span.1, self.span.1 + 1),
+            _ => self.span,
+        };
+        annotate(source, span, &format!("parse error: {}", self))
+    }
+}
+
+//依次标注所有解析错误 多于一个时在最后注明错误个数
+pub fn render_errors(errors: &[ParseError], source: &str) -> String {
+    let mut text = errors.iter().map(|err| err.render(source)).collect::<Vec<_>>().join("\n\n");
+    if errors.len() > 1 {
+        text.push_str(&format!("\n\n{} parse errors", errors.len()));
+    }
+    text
+}
+
+//expected `)` but found `;` 形式的错误描述
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let found = match &self.token {
+            Token::End => "end of input".to_string(),
+            Token::Illegal(c) => format!("illegal character `{}`", c),
+            token => format!("`{}`", token),
+        };
+        match self.expected.as_slice() {
+            [] => write!(f, "unexpected {}", found),
+            [expected] => write!(f, "expected {} but found {}", expected, found),
+            [init @ .., last] => write!(f, "expected {} or {} but found {}", init.join(", "), last, found),
+        }
+    }
+}
+
+//解析由多条语句组成的完整程序 直到输入结束
+//某条语句出错时跳到下一条语句继续解析 语句块中的语句同样如此 最后一并返回所有错误
+pub fn parse_program(lexer: &mut Lexer) -> Result<Vec<ASTNode>, Vec<ParseError>> {
+    let mut nodes = vec![];
+    let mut errors = vec![];
+    while lexer.peek() != Token::End {
+        let start = lexer.position();
+        match parse_statement_recovering(lexer, &mut errors) {
+            Ok(node) => nodes.push(node),
+            Err(err) => {
+                errors.push(err);
+                synchronize(lexer, start, false);
+            },
+        }
+    }
+    if errors.is_empty() {
+        Ok(nodes)
+    } else {
+        Err(errors)
+    }
+}
+
+//从出错语句的开头跳过整条语句
+//语句在括号外的;处结束 或者在使括号恢复平衡的}处结束(后面紧跟else、elseif、catch或者;时一并跳过)
+//未闭合的()或[]中不会出现; 遇到时视为这些括号缺少了右括号 语句同样在此结束(位于{}中时继续跳到}处)
+//nested为true时语句位于语句块中 不匹配的}是语句块的结尾 语句在它之前结束
+fn synchronize(lexer: &mut Lexer, start: usize, nested: bool) {
+    lexer.seek(start);
+    let mut open = vec![];
+    loop {
+        let token = lexer.next();
+        match token {
+            Token::End => {
+                lexer.prev();
+                return
+            },
+            Token::RightBrace if nested && open.is_empty() => {
+                lexer.prev();
+                return
+            },
+            Token::SemiColon => {
+                while matches!(open.last(), Some(Token::LeftParen | Token::LeftBracket)) {
+                    open.pop();
+                }
+                if open.is_empty() {
+                    return
+                }
+            },
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => open.push(token),
+            Token::RightBrace if open.len() <= 1 => {
+                open.clear();
+                match lexer.peek() {
+                    Token::Else | Token::ElseIf | Token::Catch => (),
+                    Token::SemiColon => {
+                        lexer.next();
+                        return
+                    },
+                    _ => return,
+                }
+            },
+            Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                open.pop();
+            },
+            _ => (),
+        }
+    }
+}
+
+//解析完整的一句语句(包括结尾的;) 语句块中有多个错误时只返回第一个
 pub fn parse_statement(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
-    let node = parse_statment_ignore_end_semi_colon(lexer)?;
+    let mut errors = vec![];
+    let result = parse_statement_recovering(lexer, &mut errors);
+    match (result, errors.into_iter().next()) {
+        (_, Some(err)) => Err(err),
+        (result, None) => result,
+    }
+}
+
+//解析完整的一句语句(包括结尾的;)
+//语句块中出错的语句被跳过并记录在errors中 返回的错误是语句本身无法恢复的错误
+fn parse_statement_recovering(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    let node = parse_statment_ignore_end_semi_colon(lexer, errors)?;
     //结尾是否存在分号
     Ok(if lexer.peek() == Token::SemiColon {
         lexer.next();
@@ -22,42 +143,137 @@ pub fn parse_statement(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
 }
 
 //解析完整的一句语句(忽略语句结尾的;)
-pub fn parse_statment_ignore_end_semi_colon(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+pub fn parse_statment_ignore_end_semi_colon(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
     Ok(match (lexer.next(), lexer.next(), lexer.next()) {
         //(数值/函数)变量定义 格式:let name = expr or lambda
         (Token::Let, Token::Symbol(name), Token::Assign) => {
-            ASTNode::Assign(name, Box::new(parse_statment_ignore_end_semi_colon(lexer)?), true)
+            //变量名的位置
+            lexer.prev(); lexer.prev();
+            let span = lexer.span();
+            lexer.next(); lexer.next();
+            ASTNode::Assign(Box::new(ASTNode::Var(name, span)), Box::new(parse_assigned_value(lexer, errors)?), true)
         },
-        //变量赋值
-        (Token::Symbol(name), Token::Assign, _) => {
-            lexer.prev();
-            ASTNode::Assign(name, Box::new(parse_statment_ignore_end_semi_colon(lexer)?), false)
+        //映射字面量 {key: ...}或者{"key": ...}开头的表达式 语句开头的{}是空语句块
+        (Token::LeftBrace, Token::Symbol(_), Token::Colon) |
+        (Token::LeftBrace, Token::String(_), Token::Colon) => {
+            lexer.prev(); lexer.prev(); lexer.prev();
+            parse_expr(lexer, errors)?
         }
         //语句块
         (Token::LeftBrace, _, _) => {
             lexer.prev(); lexer.prev(); lexer.prev();
-            parse_block(lexer)?
+            parse_block(lexer, errors)?
         }
         //条件表达式
         (Token::If, _, _) => {
             lexer.prev(); lexer.prev(); lexer.prev();
-            parse_cond_expr(lexer)?
+            parse_cond_expr(lexer, errors)?
+        },
+        //while循环
+        (Token::While, _, _) => {
+            lexer.prev(); lexer.prev(); lexer.prev();
+            parse_while(lexer, errors)?
+        },
+        //for循环
+        (Token::For, _, _) => {
+            lexer.prev(); lexer.prev(); lexer.prev();
+            parse_for(lexer, errors)?
+        },
+        //跳出循环
+        (Token::Break, _, _) => {
+            lexer.prev(); lexer.prev();
+            ASTNode::Break(lexer.prev_span())
+        },
+        //进入下一次循环
+        (Token::Continue, _, _) => {
+            lexer.prev(); lexer.prev();
+            ASTNode::Continue(lexer.prev_span())
+        },
+        //函数返回 格式:return expr 或者 return
+        (Token::Return, _, _) => {
+            lexer.prev(); lexer.prev();
+            match lexer.peek() {
+                Token::SemiColon | Token::RightBrace | Token::End => ASTNode::Return(Box::new(ASTNode::Empty)),
+                _ => ASTNode::Return(Box::new(parse_expr(lexer, errors)?)),
+            }
+        },
+        //捕获错误 格式:try { ... } catch (e) { ... }
+        (Token::Try, _, _) => {
+            lexer.prev(); lexer.prev(); lexer.prev();
+            parse_try(lexer, errors)?
+        },
+        //抛出错误 格式:throw expr
+        (Token::Throw, _, _) => {
+            lexer.prev(); lexer.prev(); lexer.prev();
+            let span = lexer.span();
+            lexer.next();
+            ASTNode::Throw(Box::new(parse_expr(lexer, errors)?), span)
         },
         //单独一个分号
         (Token::SemiColon, _, _) => {
             lexer.prev(); lexer.prev();
             ASTNode::Void(Box::new(ASTNode::Empty))
         },
-        //表达式
+        //表达式 或者 变量赋值(a = expr、arr[i][j] = expr、a += expr、a++)
         _ => {
             lexer.prev(); lexer.prev(); lexer.prev();
-            parse_expr(lexer)?
+            let node = parse_expr(lexer, errors)?;
+            match lexer.peek() {
+                Token::Assign | Token::CompoundAssign(_) | Token::Increment(_) if !is_lvalue(&node) => return unexpected_token(lexer, &[]),
+                Token::Assign => {
+                    lexer.next();
+                    ASTNode::Assign(Box::new(node), Box::new(parse_assigned_value(lexer, errors)?), false)
+                },
+                Token::CompoundAssign(op) => {
+                    lexer.next();
+                    ASTNode::CompoundAssign(op, Box::new(node), Box::new(parse_assigned_value(lexer, errors)?))
+                },
+                //x++等价于x += 1; x--等价于x -= 1; 只能单独作为一句语句 没有值
+                Token::Increment(op) => {
+                    lexer.next();
+                    if !lexer.ends_statement() {
+                        return unexpected_prev_token(lexer, &[])
+                    }
+                    ASTNode::Void(Box::new(ASTNode::CompoundAssign(op, Box::new(node), Box::new(ASTNode::Literal(ASTValue::Number(1.0))))))
+                },
+                _ => node,
+            }
         },
     })
 }
 
+//解析赋值符号右侧的值 可以是条件表达式、语句块等语句
+//这里的{}是空映射而不是空语句块
+fn parse_assigned_value(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    if lexer.peek() == Token::LeftBrace {
+        lexer.next();
+        let empty = lexer.peek() == Token::RightBrace;
+        lexer.prev();
+        if empty {
+            return parse_expr(lexer, errors)
+        }
+    }
+    let value = parse_statment_ignore_end_semi_colon(lexer, errors)?;
+    //x++只能单独作为一句语句 不能作为赋值的值
+    lexer.prev();
+    if let Token::Increment(_) = lexer.peek() {
+        return unexpected_token(lexer, &[])
+    }
+    lexer.next();
+    Ok(value)
+}
+
+//判断表达式能否出现在赋值符号左侧
+fn is_lvalue(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Var(..) => true,
+        ASTNode::Index(arr, ..) => is_lvalue(arr),
+        _ => false,
+    }
+}
+
 //解析输入的表达式
-pub fn parse_expr(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+pub fn parse_expr(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
     //表达式有两种 一种是lambda表达式 一种是包含着一元运算的二元运算表达式
     match (lexer.next(), lexer.next(), lexer.next(), lexer.peek()) {
         //函数表达式
@@ -68,28 +284,34 @@ pub fn parse_expr(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
         //(x, ...
         (Token::LeftParen, Token::Symbol(_), Token::Comma, _) => {
             lexer.prev(); lexer.prev(); lexer.prev();
-            parse_lambda(lexer)
+            parse_lambda(lexer, errors)
         },
         //数值表达式
         _ => {
             lexer.prev(); lexer.prev(); lexer.prev();
-            parse_binary_op(lexer, 1)
+            parse_binary_op(lexer, errors, 0)
         },
     }
 }
 
 //解析生成双目运算节点
 //将原本Express Term Factor多个层级的递归计算通过优先级定义统一成了单一函数
-fn parse_binary_op(lexer: &mut Lexer, level: i32) -> Result<ASTNode, ParseError> {
-    let mut node = parse_unitary_op(lexer)?;
+fn parse_binary_op(lexer: &mut Lexer, errors: &mut Vec<ParseError>, level: i32) -> Result<ASTNode, ParseError> {
+    let mut node = parse_unitary_op(lexer, errors)?;
     loop {
         if let Token::Operator(op) = lexer.peek() {
             //假定level这个优先级的运算符写为+
             //则由level这个优先级的运算符构成的双目表达式为T (+T) (+T) (+T).....
             //其中T包含着更高优先级的运算构成的双目表达式
+            //右结合的运算符右侧的T还可以包含同一优先级的运算
             if level <= op.priority() {
+                let span = lexer.span();
                 lexer.next();
-                node = ASTNode::Binary(op, Box::new(node), Box::new(parse_binary_op(lexer, op.priority() + 1)?));
+                let next_level = match op.assoc() {
+                    Assoc::Left => op.priority() + 1,
+                    Assoc::Right => op.priority(),
+                };
+                node = ASTNode::Binary(op, Box::new(node), Box::new(parse_binary_op(lexer, errors, next_level)?), span);
                 continue
             }
         }
@@ -143,20 +365,24 @@ fn parse_mul_div_pow_mod(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
 /////////////////////////////////////////////////////////////////////////////////////
 
 //解析生成单目运算
-fn parse_unitary_op(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+//操作数中可以包含优先级比单目运算更高的双目运算(乘方)
+fn parse_unitary_op(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
     if let Token::Operator(op) = lexer.peek() {
         if op == Op::Add || op == Op::Sub || op == Op::Not {
+            let span = lexer.span();
             lexer.next();
-            return Ok(ASTNode::Unitary(op, Box::new(parse_unitary_op(lexer)?)))
+            return Ok(ASTNode::Unitary(op, Box::new(parse_binary_op(lexer, errors, Op::UNITARY_PRIORITY + 1)?), span))
         }
     }
-    parse_minimum_item(lexer)
+    parse_minimum_item(lexer, errors)
 }
 
 //解析最小表达式单元
 //一般形式为A、A(x, ...)、A[i]、或者混合形式A()[]() ....
 //其中A的形式包括(expr)、逻辑字面量、数字字面量、变量标识符、数组
-fn parse_minimum_item(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+fn parse_minimum_item(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    //A的起始位置 调用和索引节点的位置从这里开始
+    let begin = lexer.span();
     //先解析A
     let mut node = match lexer.next() {
         //逻辑字面量
@@ -166,21 +392,27 @@ fn parse_minimum_item(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
             if let Ok(x) = num.parse() {
                 ASTNode::Literal(ASTValue::Number(x))
             } else {
-                return unexpected_prev_token(lexer)
+                return unexpected_prev_token(lexer, &[])
             }
         },
+        //字符串字面量
+        Token::String(s) => ASTNode::Literal(ASTValue::String(s.into())),
+        //插值字符串
+        Token::Template(parts) => parse_template(parts, lexer.prev_span(), errors)?,
         //变量标识符
-        Token::Symbol(name) => ASTNode::Var(name),
+        Token::Symbol(name) => ASTNode::Var(name, lexer.prev_span()),
         //数组[1, 2, 3, ...]
-        Token::LeftBracket => ASTNode::Array(parse_list(lexer, &Token::RightBracket)?),
+        Token::LeftBracket => ASTNode::Array(parse_list(lexer, errors, &Token::RightBracket)?),
+        //映射{key: value, ...}
+        Token::LeftBrace => parse_map(lexer, errors)?,
         //(表达式)
         Token::LeftParen => {
-            let node = parse_expr(lexer)?;
-            expect_token(lexer, &Token::RightParen)?;
+            let node = parse_expr(lexer, errors)?;
+            expect_token(lexer, &[Token::RightParen])?;
             node
         }
         //其余的情况先不管了
-        _ => return unexpected_prev_token(lexer),
+        _ => return unexpected_prev_token(lexer, &["expression"]),
     };
 
     //在解析A后面的(x, ...)、[i, j]或者混合形式A()[]() ....
@@ -188,14 +420,27 @@ fn parse_minimum_item(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
     loop {
         node = match lexer.next() {
             Token::LeftParen => {
-                ASTNode::Apply(Box::new(node), parse_list(lexer, &Token::RightParen)?)
+                let args = parse_list(lexer, errors, &Token::RightParen)?;
+                ASTNode::Apply(Box::new(node), args, Span(begin.0, lexer.prev_span().1))
             },
             Token::LeftBracket => {
-                let indices = parse_list(lexer, &Token::RightBracket)?;
-                if indices.len() > 1 {
-                    ASTNode::Index(Box::new(node), Box::new(ASTNode::Array(indices)))
-                } else {
-                    ASTNode::Index(Box::new(node), Box::new(indices[0].clone()))
+                let mut indices = parse_list(lexer, errors, &Token::RightBracket)?;
+                let span = Span(begin.0, lexer.prev_span().1);
+                match indices.len() {
+                    //a[]中缺少下标
+                    0 => return unexpected_prev_token(lexer, &["expression"]),
+                    1 => ASTNode::Index(Box::new(node), Box::new(indices.remove(0)), span),
+                    _ => ASTNode::Index(Box::new(node), Box::new(ASTNode::Array(indices)), span),
+                }
+            }
+            //m.key等价于m["key"]
+            Token::Dot => {
+                match lexer.next() {
+                    Token::Symbol(key) => {
+                        let span = Span(begin.0, lexer.prev_span().1);
+                        ASTNode::Index(Box::new(node), Box::new(ASTNode::Literal(ASTValue::String(key.into()))), span)
+                    },
+                    _ => return unexpected_prev_token(lexer, &["identifier"]),
                 }
             }
             _ => {
@@ -208,32 +453,78 @@ fn parse_minimum_item(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
     Ok(node)
 }
 
+//解析插值字符串 "x = {x}" 被展开为 "x = " + x 形式的字符串拼接
+//拼接总是从字符串开始 因此每个嵌入表达式的值都按显示格式转换为字符串
+//span为整个字符串字面量的位置
+fn parse_template(parts: Vec<TemplatePart>, span: Span, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    let mut node = ASTNode::Literal(ASTValue::String("".into()));
+    for (i, part) in parts.into_iter().enumerate() {
+        let item = match part {
+            TemplatePart::Text(s) => ASTNode::Literal(ASTValue::String(s.into())),
+            TemplatePart::Expr(src, offset) => {
+                //用单独的词法分析器解析嵌入表达式 符号位置按整个输入计算
+                let mut lexer = Lexer::with_offset(&src, offset);
+                let expr = parse_expr(&mut lexer, errors)?;
+                if lexer.peek() != Token::End {
+                    return unexpected_token(&lexer, &["`}`"])
+                }
+                expr
+            },
+        };
+        node = match (i, item) {
+            (0, item @ ASTNode::Literal(_)) => item,
+            (_, item) => ASTNode::Binary(Op::Add, Box::new(node), Box::new(item), span),
+        };
+    }
+    Ok(node)
+}
+
 //解析列表x, y, z, ...) 或者x, y, z, ...]
-fn parse_list(lexer: &mut Lexer, closing: &Token) -> Result<Vec<ASTNode>, ParseError> {
+fn parse_list(lexer: &mut Lexer, errors: &mut Vec<ParseError>, closing: &Token) -> Result<Vec<ASTNode>, ParseError> {
     let mut args = vec![];
     while lexer.peek() != *closing {
-        args.push(parse_expr(lexer)?);
+        args.push(parse_expr(lexer, errors)?);
         if lexer.peek() == Token::Comma {
             lexer.next();
         } else {
             break;
         }
     }
-    expect_token(lexer, closing)?;
+    expect_token(lexer, &[Token::Comma, closing.clone()])?;
     Ok(args)
 }
 
+//解析映射key: value, "key": value, ...} 键可以是标识符或者字符串
+fn parse_map(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    let mut entries = vec![];
+    while lexer.peek() != Token::RightBrace {
+        let key = match lexer.next() {
+            Token::Symbol(key) | Token::String(key) => key,
+            _ => return unexpected_prev_token(lexer, &["identifier", "string"]),
+        };
+        expect_token(lexer, &[Token::Colon])?;
+        entries.push((key, parse_expr(lexer, errors)?));
+        if lexer.peek() == Token::Comma {
+            lexer.next();
+        } else {
+            break;
+        }
+    }
+    expect_token(lexer, &[Token::Comma, Token::RightBrace])?;
+    Ok(ASTNode::Map(entries))
+}
+
 //解析lambda表达式
-fn parse_lambda(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+fn parse_lambda(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
     Ok(match (lexer.next(), lexer.next(), lexer.next(), lexer.next()) {
         //() => body
         (Token::LeftParen, Token::RightParen, Token::Arrow, _) => {
             lexer.prev();
-            ASTNode::Lambda(vec![], Box::new(parse_block(lexer)?))
+            ASTNode::Lambda(vec![], Rc::new(parse_block(lexer, errors)?))
         },
         //(x) => body
         (Token::LeftParen, Token::Symbol(x), Token::RightParen, Token::Arrow) => {
-            ASTNode::Lambda(vec![x], Box::new(parse_block(lexer)?))
+            ASTNode::Lambda(vec![x], Rc::new(parse_block(lexer, errors)?))
         },
         //(x, y, ...) => body
         (Token::LeftParen, Token::Symbol(x), Token::Comma, Token::Symbol(y)) => {
@@ -242,69 +533,73 @@ fn parse_lambda(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
                 match lexer.next() {
                     Token::Comma => (),
                     Token::RightParen => break,
-                    _ => return unexpected_prev_token(lexer),
+                    _ => return unexpected_prev_token(lexer, &["`,`", "`)`"]),
                 };
 
                 match lexer.next() {
                     Token::Symbol(z) => args.push(z),
-                    _ => return unexpected_prev_token(lexer),
+                    _ => return unexpected_prev_token(lexer, &["identifier"]),
                 }
             }
-            expect_token(lexer, &Token::Arrow)?;
-            ASTNode::Lambda(args, Box::new(parse_block(lexer)?))
+            expect_token(lexer, &[Token::Arrow])?;
+            ASTNode::Lambda(args, Rc::new(parse_block(lexer, errors)?))
         },
-        _ => return unexpected_prev_token(lexer),
+        _ => return unexpected_prev_token(lexer, &["identifier"]),
     })
 }
 
 //解析语句块
-fn parse_block(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+fn parse_block(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
     let mut nodes = vec![];
     if lexer.next() != Token::LeftBrace {
-        return unexpected_prev_token(lexer);
+        return unexpected_prev_token(lexer, &["`{`"]);
     }
-    loop {
-        nodes.push(parse_statement(lexer)?);
-        //一直解析到右大括号出线
-        if lexer.peek() == Token::RightBrace {
-            lexer.next();
-            break
+    //一直解析到右大括号出线 {}为空语句块
+    while lexer.peek() != Token::RightBrace {
+        //语句块没有闭合
+        if lexer.peek() == Token::End {
+            return unexpected_token(lexer, &["`}`"]);
+        }
+        //出错的语句记录下来后跳过 继续解析语句块中其余的语句
+        //输入提前结束时无法恢复 交给外层报告
+        let start = lexer.position();
+        match parse_statement_recovering(lexer, errors) {
+            Ok(node) => nodes.push(node),
+            Err(err) if err.token == Token::End => return Err(err),
+            Err(err) => {
+                errors.push(err);
+                synchronize(lexer, start, true);
+            },
         }
     }
+    lexer.next();
     Ok(ASTNode::Block(nodes))
 }
 
 //解析条件表达式
-fn parse_cond_expr(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+fn parse_cond_expr(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
     //解析if条件及分支语句块
     let if_node = match lexer.next() {
         Token::If => {
-            let cond = parse_expr(lexer)?;
-            Box::new((cond, parse_block(lexer)?))
+            let cond = parse_expr(lexer, errors)?;
+            Box::new((cond, parse_block(lexer, errors)?))
         },
         _ => {
-            return unexpected_prev_token(lexer)
+            return unexpected_prev_token(lexer, &["`if`"])
         },
     };
     //解析复数个elseif条件及分支语句块
     let mut elseif_nodes = vec![];
-    loop {
-        match lexer.peek() {
-            Token::ElseIf => {
-                lexer.next();
-                let cond = parse_expr(lexer)?;
-                elseif_nodes.push((cond, parse_block(lexer)?));
-            }
-            _ => {
-                break;
-            },
-        };
+    while let Token::ElseIf = lexer.peek() {
+        lexer.next();
+        let cond = parse_expr(lexer, errors)?;
+        elseif_nodes.push((cond, parse_block(lexer, errors)?));
     }
     //解析else分支语句块
     let else_node = match lexer.peek() {
         Token::Else => {
             lexer.next();
-            Some(Box::new(parse_block(lexer)?))
+            Some(Box::new(parse_block(lexer, errors)?))
         },
         _ => None,
     };
@@ -312,22 +607,63 @@ fn parse_cond_expr(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
     Ok(ASTNode::Cond(if_node, elseif_nodes, else_node))
 }
 
+//解析while循环 格式:while cond { ... }
+fn parse_while(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    expect_token(lexer, &[Token::While])?;
+    let cond = parse_expr(lexer, errors)?;
+    Ok(ASTNode::While(Box::new(cond), Box::new(parse_block(lexer, errors)?)))
+}
+
+//解析for循环 格式:for x in expr { ... }
+fn parse_for(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    expect_token(lexer, &[Token::For])?;
+    let name = match lexer.next() {
+        Token::Symbol(name) => name,
+        _ => return unexpected_prev_token(lexer, &["identifier"]),
+    };
+    expect_token(lexer, &[Token::In])?;
+    let iterable = parse_expr(lexer, errors)?;
+    Ok(ASTNode::For(name, Box::new(iterable), Box::new(parse_block(lexer, errors)?)))
+}
+
+//解析错误捕获 格式:try { ... } catch (e) { ... }
+fn parse_try(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<ASTNode, ParseError> {
+    expect_token(lexer, &[Token::Try])?;
+    let body = parse_block(lexer, errors)?;
+    expect_token(lexer, &[Token::Catch])?;
+    expect_token(lexer, &[Token::LeftParen])?;
+    let name = match lexer.next() {
+        Token::Symbol(name) => name,
+        _ => return unexpected_prev_token(lexer, &["identifier"]),
+    };
+    expect_token(lexer, &[Token::RightParen])?;
+    Ok(ASTNode::Try(Box::new(body), name, Box::new(parse_block(lexer, errors)?)))
+}
+
 //错误处理
-fn unexpected_token(lexer: &Lexer) -> Result<ASTNode, ParseError> {
+//expected列出此处期望出现的内容
+fn unexpected_token(lexer: &Lexer, expected: &[&str]) -> Result<ASTNode, ParseError> {
     raise!(ParseError {
         token: lexer.peek(),
         span: lexer.span(),
+        expected: expected.iter().map(|item| item.to_string()).collect(),
     })
 }
 
-fn unexpected_prev_token(lexer: &mut Lexer) -> Result<ASTNode, ParseError> {
+fn unexpected_prev_token(lexer: &mut Lexer, expected: &[&str]) -> Result<ASTNode, ParseError> {
     lexer.prev();
-    unexpected_token(lexer)
+    unexpected_token(lexer, expected)
 }
 
-fn expect_token(lexer: &mut Lexer, token: &Token) -> Result<(), ParseError> {
-    if lexer.next() != *token {
-        unexpected_prev_token(lexer)?;
+//下一个符号必须是tokens中的一个 否则报错并列出所有期望的符号
+fn expect_token(lexer: &mut Lexer, tokens: &[Token]) -> Result<(), ParseError> {
+    if !tokens.contains(&lexer.next()) {
+        lexer.prev();
+        raise!(ParseError {
+            token: lexer.peek(),
+            span: lexer.span(),
+            expected: tokens.iter().map(|token| format!("`{}`", token)).collect(),
+        })
     }
     Ok(())
-}
\ No newline at end of file
+}
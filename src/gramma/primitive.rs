@@ -2,26 +2,38 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use crate::gramma::environment::Environment;
 use crate::gramma::ast::{ASTValue, Callable};
+use crate::gramma::error::{ErrorKind, RuntimeError};
+use crate::gramma::evaluator::{max_call_depth, set_max_call_depth};
 
 //系统函数(非用户定义)
 struct PrimitiveFun<F>(String, F);
 impl<F> Callable for PrimitiveFun<F>
 where
-    F: Fn(&[ASTValue], Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String>,
+    F: Fn(&[ASTValue], Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError>,
 {
     fn name(&self) -> Option<&str> {
         Some(&self.0)
     }
-    fn call(&self, args: &[ASTValue], env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
-        self.1(args, env)
+    //check_args_num不知道函数名 实参数量错误在这里补上函数名
+    fn call(&self, args: &[ASTValue], env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError> {
+        self.1(args, env).map_err(|mut err| {
+            if let ErrorKind::Arity { name: name @ None, .. } = &mut err.kind {
+                *name = Some(self.0.clone());
+            }
+            err
+        })
     }
 }
 
 //创建解释器全局环境
 use std::f64::{self, consts};
-pub fn create_global_environment() -> Rc<RefCell<Environment>> {
+//args为传给脚本的命令行参数 以字符串数组的形式注册为变量args
+pub fn create_global_environment(args: &[String]) -> Rc<RefCell<Environment>> {
     let env = Rc::new(RefCell::new(Environment::new(None)));
 
+    //命令行参数
+    env.borrow_mut().regist("args", ASTValue::Array(args.iter().map(|arg| ASTValue::String(arg.as_str().into())).collect()));
+
     //常量
     regist_const(env.clone(), "pi", consts::PI);
     regist_const(env.clone(), "e", consts::E);
@@ -50,9 +62,80 @@ pub fn create_global_environment() -> Rc<RefCell<Environment>> {
     //注册一些有用的广义函数
     regist_genneral_fun(env.clone());
 
+    //注册字符串处理函数
+    regist_string_fun(env.clone());
+
+    //注册映射处理函数
+    regist_map_fun(env.clone());
+
     env
 }
 
+//最近几次求值结果的变量名 ans为最近一次 ans1为上一次 依此类推
+pub const ANSWERS: [&str; 3] = ["ans", "ans1", "ans2"];
+
+//把求值结果注册为ans 之前的结果依次后移
+pub fn regist_answer(env: Rc<RefCell<Environment>>, value: ASTValue) {
+    let mut env = env.borrow_mut();
+    for i in (1..ANSWERS.len()).rev() {
+        if let Some(prev) = env.get(ANSWERS[i - 1], true) {
+            env.regist(ANSWERS[i], prev);
+        }
+    }
+    env.regist(ANSWERS[0], value);
+}
+
+//原始函数和常量的说明 REPL中:help name显示
+const HELP: &[(&str, &str)] = &[
+    ("pi", "pi: the circle constant 3.14159..."),
+    ("e", "e: the base of natural logarithm 2.71828..."),
+    ("nan", "nan: not a number"),
+    ("inf", "inf: positive infinity"),
+    ("abs", "abs(x): absolute value of x"),
+    ("sqrt", "sqrt(x): square root of x"),
+    ("ln", "ln(x): natural logarithm of x"),
+    ("log2", "log2(x): base 2 logarithm of x"),
+    ("log10", "log10(x): base 10 logarithm of x"),
+    ("round", "round(x): x rounded to the nearest integer"),
+    ("floor", "floor(x): the largest integer not greater than x"),
+    ("sin", "sin(x): sine of x in radians"),
+    ("cos", "cos(x): cosine of x in radians"),
+    ("tan", "tan(x): tangent of x in radians"),
+    ("asin", "asin(x): arcsine of x in radians"),
+    ("acos", "acos(x): arccosine of x in radians"),
+    ("atan", "atan(x): arctangent of x in radians"),
+    ("log", "log(x, base): logarithm of x with the given base"),
+    ("atan2", "atan2(y, x): four quadrant arctangent of y / x"),
+    ("map", "map(arr, f): array of f applied to every element of arr"),
+    ("length", "length(x): number of elements of an array or a map, or characters of a string"),
+    ("len", "len(x): same as length(x)"),
+    ("range", "range(start, end): array of integers from start up to but not including end"),
+    ("max_call_depth", "max_call_depth() or max_call_depth(n): query or set the maximum function call depth, returns the old value"),
+    ("error", "error(message): raise an error of kind \"error\" that try ... catch can handle"),
+    ("linespace", "linespace(start, end, n): array of n evenly spaced numbers from start to end"),
+    ("upper", "upper(s): s in upper case"),
+    ("lower", "lower(s): s in lower case"),
+    ("trim", "trim(s): s without leading and trailing whitespace"),
+    ("substr", "substr(s, start, count): count characters of s beginning at start"),
+    ("split", "split(s, sep): array of the parts of s separated by sep, single characters if sep is empty"),
+    ("join", "join(arr, sep): elements of arr joined by sep into a string"),
+    ("replace", "replace(s, from, to): s with every from replaced by to"),
+    ("find", "find(s, pattern): character index of the first pattern in s, -1 if not found"),
+    ("keys", "keys(m): array of the keys of map m in insertion order"),
+    ("values", "values(m): array of the values of map m in insertion order"),
+    ("has", "has(m, key): whether map m contains key"),
+    ("remove", "remove(m, key): new map without key, m itself is unchanged"),
+    ("args", "args: array of the command line arguments passed to the script"),
+    ("ans", "ans: the value of the last statement that produced one"),
+    ("ans1", "ans1: the value before ans"),
+    ("ans2", "ans2: the value before ans1"),
+];
+
+//查找原始函数或常量的说明
+pub fn primitive_help(name: &str) -> Option<&'static str> {
+    HELP.iter().find(|(key, _)| *key == name).map(|(_, help)| *help)
+}
+
 //注册一些有用的广义函数
 fn regist_genneral_fun(env: Rc<RefCell<Environment>>) {
     //数组映射函数
@@ -63,27 +146,20 @@ fn regist_genneral_fun(env: Rc<RefCell<Environment>>) {
             (ASTValue::Array(elements), ASTValue::Function(fun)) => {
                 let mut results = vec![];
                 for element in elements.iter() {
-                    if let Some(result) = fun.call(&[element.clone()], env.clone())? {
+                    if let Some(result) = fun.call(std::slice::from_ref(element), env.clone())? {
                         results.push(result)
                     }
                 }
                 Ok(Some(ASTValue::Array(results.into())))
             },
-            _ => {
-                raise!("map error")
-            }
+            (ASTValue::Array(_), fun) => raise!(ErrorKind::NotCallable(fun.type_name())),
+            (arr, _) => raise!(ErrorKind::WrongType { expected: "array", found: arr.type_name() }),
         }
     });
 
-    //数组长度
-    regist_primivitive_fun(env.clone(), "length", |args, _| {
-        let args = check_args_num(args, 1)?;
-        if let ASTValue::Array(arr) = &args[0] {
-            Ok(Some(ASTValue::Number(arr.len() as f64)))
-        } else {
-            raise!("not an array")
-        }
-    });
+    //数组长度(字符串则为字符个数)
+    regist_primivitive_fun(env.clone(), "length", length);
+    regist_primivitive_fun(env.clone(), "len", length);
 
     //range
     regist_primivitive_fun(env.clone(), "range", |args, _| {
@@ -91,7 +167,7 @@ fn regist_genneral_fun(env: Rc<RefCell<Environment>>) {
         let i0 = args[0].f64()?.round() as i64;
         let i1 = args[1].f64()?.round() as i64;
 
-        let mut i = 0 as i64;
+        let mut i = 0_i64;
         let mut arr = vec![];
 
         while i0 + i < i1 {
@@ -102,6 +178,24 @@ fn regist_genneral_fun(env: Rc<RefCell<Environment>>) {
         Ok(Some(ASTValue::Array(arr.into())))
     });
 
+    //查询或设置允许的最大函数调用深度
+    //max_call_depth()返回当前值 max_call_depth(n)设置新值并返回旧值
+    regist_primivitive_fun(env.clone(), "max_call_depth", |args, _| {
+        let old = max_call_depth();
+        match args {
+            [] => (),
+            [depth] => set_max_call_depth(depth.f64()?.max(0.0) as usize),
+            _ => raise!(ErrorKind::Arity { name: None, expected: 1, found: args.len() }),
+        }
+        Ok(Some(ASTValue::Number(old as f64)))
+    });
+
+    //抛出种类为error的错误 与throw message相同
+    regist_primivitive_fun(env.clone(), "error", |args, _| {
+        let args = check_args_num(args, 1)?;
+        raise!(ErrorKind::User { kind: "error".into(), message: args[0].string()?.to_string() })
+    });
+
     //linespace
     regist_primivitive_fun(env.clone(), "linespace", |args, _| {
         let args = check_args_num(args, 3)?;
@@ -110,28 +204,165 @@ fn regist_genneral_fun(env: Rc<RefCell<Environment>>) {
         let n = args[2].f64()?.floor() as i64;
 
         if n < 2 {
-            raise!("number of steps cannot be less than 2")
+            raise!(ErrorKind::InvalidArgument("number of steps cannot be less than 2"))
         }
 
         let arr = (0..n)
             .map(|i| (i as f64) / ((n - 1) as f64))
             .map(|v| (1.0 - v) * t0 + v * t1)
-            .map(|v| ASTValue::Number(v))
+            .map(ASTValue::Number)
             .collect::<Vec<_>>();
 
             Ok(Some(ASTValue::Array(arr.into())))
     });
 }
 
+//数组长度或者字符串的字符个数
+fn length(args: &[ASTValue], _: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError> {
+    let args = check_args_num(args, 1)?;
+    match &args[0] {
+        ASTValue::Array(arr) => Ok(Some(ASTValue::Number(arr.len() as f64))),
+        ASTValue::String(s) => Ok(Some(ASTValue::Number(s.chars().count() as f64))),
+        ASTValue::Map(entries) => Ok(Some(ASTValue::Number(entries.len() as f64))),
+        arg => raise!(ErrorKind::WrongType { expected: "array, map or string", found: arg.type_name() }),
+    }
+}
+
+//注册字符串处理函数
+//所有位置和长度都以字符(而非字节)为单位
+fn regist_string_fun(env: Rc<RefCell<Environment>>) {
+    regist_string_unitary_fun(env.clone(), "upper", |s| s.to_uppercase());
+    regist_string_unitary_fun(env.clone(), "lower", |s| s.to_lowercase());
+    regist_string_unitary_fun(env.clone(), "trim", |s| s.trim().to_string());
+
+    //子串 substr("hello", 1, 3) = "ell"
+    regist_primivitive_fun(env.clone(), "substr", |args, _| {
+        let args = check_args_num(args, 3)?;
+        let s = args[0].string()?;
+        let (start, count) = (args[1].f64()?, args[2].f64()?);
+        //起点可以等于长度(得到空串) 负数和非整数的起点视为越界
+        let len = s.chars().count();
+        if start < 0.0 || start.fract() != 0.0 || start > len as f64 {
+            raise!(ErrorKind::IndexOutOfBounds { index: start, len })
+        }
+        if count < 0.0 || count.fract() != 0.0 {
+            raise!(ErrorKind::InvalidArgument("count must be a non-negative integer"))
+        }
+        let result = s.chars().skip(start as usize).take(count as usize).collect::<String>();
+        Ok(Some(ASTValue::String(result.into())))
+    });
+
+    //分割 split("a,b,c", ",") = ["a", "b", "c"] 分隔符为空时分割为单个字符
+    regist_primivitive_fun(env.clone(), "split", |args, _| {
+        let args = check_args_num(args, 2)?;
+        let s = args[0].string()?;
+        let sep = args[1].string()?;
+        let parts = if sep.is_empty() {
+            s.chars().map(|c| ASTValue::String(c.to_string().into())).collect::<Vec<_>>()
+        } else {
+            s.split(&*sep).map(|part| ASTValue::String(part.into())).collect::<Vec<_>>()
+        };
+        Ok(Some(ASTValue::Array(parts.into())))
+    });
+
+    //连接 join(["a", "b", "c"], "-") = "a-b-c" 非字符串元素按显示格式转换
+    regist_primivitive_fun(env.clone(), "join", |args, _| {
+        let args = check_args_num(args, 2)?;
+        let sep = args[1].string()?;
+        match &args[0] {
+            ASTValue::Array(elements) => {
+                let parts = elements.iter().map(|element| element.to_string()).collect::<Vec<_>>();
+                Ok(Some(ASTValue::String(parts.join(&sep).into())))
+            },
+            arg => raise!(ErrorKind::WrongType { expected: "array", found: arg.type_name() }),
+        }
+    });
+
+    //替换 replace("a-b-c", "-", "+") = "a+b+c"
+    regist_primivitive_fun(env.clone(), "replace", |args, _| {
+        let args = check_args_num(args, 3)?;
+        let s = args[0].string()?;
+        let from = args[1].string()?;
+        let to = args[2].string()?;
+        Ok(Some(ASTValue::String(s.replace(&*from, &to).into())))
+    });
+
+    //查找子串第一次出现的位置 找不到时返回-1
+    regist_primivitive_fun(env.clone(), "find", |args, _| {
+        let args = check_args_num(args, 2)?;
+        let s = args[0].string()?;
+        let pattern = args[1].string()?;
+        let index = match s.find(&*pattern) {
+            Some(i) => s[..i].chars().count() as f64,
+            None => -1.0,
+        };
+        Ok(Some(ASTValue::Number(index)))
+    });
+}
+
+//注册映射处理函数
+fn regist_map_fun(env: Rc<RefCell<Environment>>) {
+    //按插入顺序返回所有键
+    regist_primivitive_fun(env.clone(), "keys", |args, _| {
+        let args = check_args_num(args, 1)?;
+        let entries = check_map(&args[0])?;
+        let keys = entries.iter().map(|(key, _)| ASTValue::String(key.as_str().into())).collect::<Vec<_>>();
+        Ok(Some(ASTValue::Array(keys.into())))
+    });
+
+    //按插入顺序返回所有值
+    regist_primivitive_fun(env.clone(), "values", |args, _| {
+        let args = check_args_num(args, 1)?;
+        let entries = check_map(&args[0])?;
+        let values = entries.iter().map(|(_, value)| value.clone()).collect::<Vec<_>>();
+        Ok(Some(ASTValue::Array(values.into())))
+    });
+
+    //是否包含某个键
+    regist_primivitive_fun(env.clone(), "has", |args, _| {
+        let args = check_args_num(args, 2)?;
+        check_map(&args[0])?;
+        Ok(Some(ASTValue::Boolean(args[0].get_key(&args[1].string()?).is_some())))
+    });
+
+    //返回删除某个键之后的新映射 原映射不变
+    regist_primivitive_fun(env.clone(), "remove", |args, _| {
+        let args = check_args_num(args, 2)?;
+        let entries = check_map(&args[0])?;
+        let key = args[1].string()?;
+        let rest = entries.iter().filter(|(k, _)| **k != *key).cloned().collect::<Vec<_>>();
+        Ok(Some(ASTValue::Map(Rc::new(rest))))
+    });
+}
+
+//校验参数是否为映射
+fn check_map(arg: &ASTValue) -> Result<&[(String, ASTValue)], RuntimeError> {
+    match arg {
+        ASTValue::Map(entries) => Ok(entries),
+        _ => raise!(ErrorKind::WrongType { expected: "map", found: arg.type_name() }),
+    }
+}
+
+//注册一元字符串函数
+fn regist_string_unitary_fun<F>(env: Rc<RefCell<Environment>>, key: &str, fun: F)
+where
+    F: Fn(&str) -> String + 'static,
+{
+    regist_primivitive_fun(env, key, move |args: &[ASTValue], _| {
+        let args = check_args_num(args, 1)?;
+        Ok(Some(ASTValue::String(fun(&args[0].string()?).into())))
+    });
+}
+
 //注册常量
 fn regist_const(env: Rc<RefCell<Environment>>, key: &str, val: f64) {
     env.borrow_mut().regist(key, ASTValue::Number(val));
 }
 
 //注册一元函数
-fn regist_unitary_fun<F: 'static>(env: Rc<RefCell<Environment>>, key: &str, fun: F)
+fn regist_unitary_fun<F>(env: Rc<RefCell<Environment>>, key: &str, fun: F)
 where
-    F: Fn(f64) -> f64,
+    F: Fn(f64) -> f64 + 'static,
 {
     regist_primivitive_fun(env, key, move |args: &[ASTValue], _| {
         let args = check_args_num(args, 1)?;
@@ -140,9 +371,9 @@ where
 }
 
 //注册二元函数
-fn regist_binary_fun<F: 'static>(env: Rc<RefCell<Environment>>, key: &str, fun: F)
+fn regist_binary_fun<F>(env: Rc<RefCell<Environment>>, key: &str, fun: F)
 where
-    F: Fn(f64, f64) -> f64,
+    F: Fn(f64, f64) -> f64 + 'static,
 {
     regist_primivitive_fun(env, key, move |args: &[ASTValue], _| {
         let args = check_args_num(args, 2)?;
@@ -151,18 +382,18 @@ where
 }
 
 //校验输入参数的数量
-fn check_args_num<'a>(args: &'a [ASTValue], num: usize) -> Result<&'a [ASTValue], String> {
+fn check_args_num(args: &[ASTValue], num: usize) -> Result<&[ASTValue], RuntimeError> {
     if args.len() == num {
         Ok(args)
     } else {
-        raise!("arguement num wrong")
+        raise!(ErrorKind::Arity { name: None, expected: num, found: args.len() })
     }
 }
 
 //Rc要求F必须加上'static的声明周期约束
-fn regist_primivitive_fun<F: 'static>(env: Rc<RefCell<Environment>>, key: &str, fun: F)
+fn regist_primivitive_fun<F>(env: Rc<RefCell<Environment>>, key: &str, fun: F)
 where
-    F: Fn(&[ASTValue], Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String>, {
+    F: Fn(&[ASTValue], Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError> + 'static, {
         env.borrow_mut().regist(key, ASTValue::Function(Rc::new(PrimitiveFun(key.to_string(), fun))));
 }
 
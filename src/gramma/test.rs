@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod lexer {
-    use crate::gramma::token::{Token, Op};
-    use crate::gramma::lexer::Lexer;
+    use crate::gramma::token::{Token, Op, TemplatePart};
+    use crate::gramma::lexer::{Lexer, Span};
 
     #[test]
     fn input1() {
@@ -53,25 +53,210 @@ mod lexer {
         assert_eq!(lexer.next(), Token::Symbol("_fds".into()));
         assert_eq!(lexer.next(), Token::End);
     }
+
+    #[test]
+    fn number() {
+        let mut lexer = Lexer::new("1e-6 6.02E23 0xFF 0b1010 1_000_000 .5 2.5e+3 0xe-1 m.key");
+        assert_eq!(lexer.next(), Token::Number("1e-6".into()));
+        assert_eq!(lexer.next(), Token::Number("6.02E23".into()));
+        assert_eq!(lexer.next(), Token::Number("255".into()));
+        assert_eq!(lexer.next(), Token::Number("10".into()));
+        assert_eq!(lexer.next(), Token::Number("1000000".into()));
+        assert_eq!(lexer.next(), Token::Number(".5".into()));
+        assert_eq!(lexer.next(), Token::Number("2.5e+3".into()));
+        assert_eq!(lexer.next(), Token::Number("14".into()));
+        assert_eq!(lexer.next(), Token::Operator(Op::Sub));
+        assert_eq!(lexer.next(), Token::Number("1".into()));
+        assert_eq!(lexer.next(), Token::Symbol("m".into()));
+        assert_eq!(lexer.next(), Token::Dot);
+        assert_eq!(lexer.next(), Token::Symbol("key".into()));
+        assert_eq!(lexer.next(), Token::End);
+    }
+
+    #[test]
+    fn compound_assign() {
+        let mut lexer = Lexer::new("a += 1; b[0] -= 2 *= /= ^= %= == -=");
+        assert_eq!(lexer.next(), Token::Symbol("a".into()));
+        assert_eq!(lexer.next(), Token::CompoundAssign(Op::Add));
+        assert_eq!(lexer.next(), Token::Number("1".into()));
+        assert_eq!(lexer.next(), Token::SemiColon);
+        assert_eq!(lexer.next(), Token::Symbol("b".into()));
+        assert_eq!(lexer.next(), Token::LeftBracket);
+        assert_eq!(lexer.next(), Token::Number("0".into()));
+        assert_eq!(lexer.next(), Token::RightBracket);
+        assert_eq!(lexer.next(), Token::CompoundAssign(Op::Sub));
+        assert_eq!(lexer.next(), Token::Number("2".into()));
+        assert_eq!(lexer.next(), Token::CompoundAssign(Op::Mul));
+        assert_eq!(lexer.next(), Token::CompoundAssign(Op::Div));
+        assert_eq!(lexer.next(), Token::CompoundAssign(Op::Pow));
+        assert_eq!(lexer.next(), Token::CompoundAssign(Op::Mod));
+        assert_eq!(lexer.next(), Token::Operator(Op::Eq));
+        assert_eq!(lexer.next(), Token::CompoundAssign(Op::Sub));
+        assert_eq!(lexer.next(), Token::End);
+    }
+
+    #[test]
+    fn increment() {
+        //只有语句结尾处紧挨着的++、--是自增、自减
+        let mut lexer = Lexer::new("i++; v[0]-- } 1--2 a + +b x++\n");
+        assert_eq!(lexer.next(), Token::Symbol("i".into()));
+        assert_eq!(lexer.next(), Token::Increment(Op::Add));
+        assert_eq!(lexer.span(), Span(3, 4));
+        assert_eq!(lexer.next(), Token::SemiColon);
+        for _ in 0..4 {
+            lexer.next();
+        }
+        assert_eq!(lexer.next(), Token::Increment(Op::Sub));
+        assert_eq!(lexer.next(), Token::RightBrace);
+        assert_eq!(lexer.next(), Token::Number("1".into()));
+        assert_eq!(lexer.next(), Token::Operator(Op::Sub));
+        assert_eq!(lexer.next(), Token::Operator(Op::Sub));
+        assert_eq!(lexer.next(), Token::Number("2".into()));
+        assert_eq!(lexer.next(), Token::Symbol("a".into()));
+        assert_eq!(lexer.next(), Token::Operator(Op::Add));
+        assert_eq!(lexer.next(), Token::Operator(Op::Add));
+        assert_eq!(lexer.next(), Token::Symbol("b".into()));
+        //换行同样结束语句
+        assert_eq!(lexer.next(), Token::Symbol("x".into()));
+        assert_eq!(lexer.next(), Token::Increment(Op::Add));
+        assert_eq!(lexer.next(), Token::End);
+
+        //注释中的换行同样结束语句
+        let mut lexer = Lexer::new("x++ // a\n-y z-- /* b\n */ +w");
+        assert_eq!(lexer.next(), Token::Symbol("x".into()));
+        assert_eq!(lexer.next(), Token::Increment(Op::Add));
+        assert!(lexer.ends_statement());
+        for _ in 0..3 {
+            lexer.next();
+        }
+        assert_eq!(lexer.next(), Token::Increment(Op::Sub));
+        assert!(lexer.ends_statement());
+
+        //紧跟在变量之后且后面是运算符时同样合并 由解析器报错
+        let mut lexer = Lexer::new("z++ + 1");
+        assert_eq!(lexer.next(), Token::Symbol("z".into()));
+        assert_eq!(lexer.next(), Token::Increment(Op::Add));
+        assert_eq!(lexer.next(), Token::Operator(Op::Add));
+    }
+
+    #[test]
+    fn try_catch() {
+        let mut lexer = Lexer::new("try catch throw tried");
+        assert_eq!(lexer.next(), Token::Try);
+        assert_eq!(lexer.next(), Token::Catch);
+        assert_eq!(lexer.next(), Token::Throw);
+        assert_eq!(lexer.next(), Token::Symbol("tried".into()));
+    }
+
+    #[test]
+    fn unclosed() {
+        assert!(Lexer::new("let f = (n) => {").unclosed());
+        assert!(Lexer::new("[1, [2,\n 3]").unclosed());
+        assert!(!Lexer::new("let f = (n) => {\n n\n}").unclosed());
+        assert!(!Lexer::new("\"{\" + \"(\"").unclosed());
+        assert!(!Lexer::new("1)").unclosed());
+    }
+
+    #[test]
+    fn number_error() {
+        //错误位置精确到出错的字符
+        let cases = vec![
+            ("x = 1.2.3", '.', Span(7, 8)),
+            ("1e", 'e', Span(1, 2)),
+            ("1e+", '+', Span(2, 3)),
+            ("0x", 'x', Span(1, 2)),
+            ("0xFG", 'G', Span(3, 4)),
+            ("0b102", '2', Span(4, 5)),
+            ("12abc", 'a', Span(2, 3)),
+            ("1._5", '_', Span(2, 3)),
+            //分隔符只能位于两个数字之间
+            ("1_", '_', Span(1, 2)),
+            ("1__000", '_', Span(1, 2)),
+            ("1_.5", '_', Span(1, 2)),
+            ("1e_5", '_', Span(2, 3)),
+            ("0x_F", '_', Span(2, 3)),
+            ("0b1_", '_', Span(3, 4)),
+        ];
+        for (input, c, span) in cases {
+            let mut lexer = Lexer::new(input);
+            while lexer.peek() != Token::Illegal(c) {
+                assert_ne!(lexer.next(), Token::End, "{}", input);
+            }
+            assert_eq!(lexer.span(), span, "{}", input);
+        }
+    }
+
+    #[test]
+    fn comment() {
+        let mut lexer = Lexer::new("a / b // line comment\n/* block\n * comment */ c /**/d");
+        assert_eq!(lexer.next(), Token::Symbol("a".into()));
+        assert_eq!(lexer.next(), Token::Operator(Op::Div));
+        assert_eq!(lexer.next(), Token::Symbol("b".into()));
+        assert_eq!(lexer.span(), Span(45, 46));
+        assert_eq!(lexer.next(), Token::Symbol("c".into()));
+        assert_eq!(lexer.span(), Span(51, 52));
+        assert_eq!(lexer.next(), Token::Symbol("d".into()));
+        assert_eq!(lexer.next(), Token::End);
+
+        let mut lexer = Lexer::new("x /* unclosed");
+        assert_eq!(lexer.next(), Token::Symbol("x".into()));
+        assert_eq!(lexer.span(), Span(2, 13));
+        assert_eq!(lexer.next(), Token::Illegal('/'));
+    }
+
+    #[test]
+    fn string() {
+        let mut lexer = Lexer::new(r#""a b" + "say \"hi\"\n\t\\" "unclosed"#);
+        assert_eq!(lexer.next(), Token::String("a b".into()));
+        assert_eq!(lexer.next(), Token::Operator(Op::Add));
+        assert_eq!(lexer.next(), Token::String("say \"hi\"\n\t\\".into()));
+        //没有闭合时标注开头的引号
+        assert_eq!(lexer.span(), Span(27, 28));
+        assert_eq!(lexer.next(), Token::Illegal('"'));
+        assert_eq!(lexer.next(), Token::End);
+
+        //错误的转义字符标注\q
+        let mut lexer = Lexer::new(r#""bad \q escape""#);
+        assert_eq!(lexer.span(), Span(5, 7));
+        assert_eq!(lexer.next(), Token::Illegal('q'));
+    }
+
+    #[test]
+    fn template() {
+        let mut lexer = Lexer::new(r#"x "a{x}b{f("}")}\{c\}" "{x""#);
+        assert_eq!(lexer.next(), Token::Symbol("x".into()));
+        assert_eq!(lexer.next(), Token::Template(vec![
+            TemplatePart::Text("a".into()),
+            TemplatePart::Expr("x".into(), 5),
+            TemplatePart::Text("b".into()),
+            TemplatePart::Expr("f(\"}\")".into(), 9),
+            TemplatePart::Text("{c}".into()),
+        ]));
+        assert_eq!(lexer.span(), Span(24, 25));
+        assert_eq!(lexer.next(), Token::Illegal('{'));
+    }
 }
 
 
 #[cfg(test)]
 mod evaluator {
-    use crate::gramma::lexer::Lexer;
-    use crate::gramma::primitive::create_global_environment;
+    use std::rc::Rc;
+    use crate::gramma::token::Token;
+    use crate::gramma::lexer::{Lexer, Span};
+    use crate::gramma::primitive::{create_global_environment, primitive_help, regist_answer};
     use crate::gramma::ast::ASTValue;
-    use crate::gramma::parser::parse_statement;
-    use crate::gramma::evaluator::evaluate_node;
+    use crate::gramma::parser::{parse_statement, parse_program};
+    use crate::gramma::evaluator::{evaluate_statement, DEFAULT_STACK_SIZE};
+    use crate::gramma::error::{ErrorKind, RuntimeError, Frame};
 
     fn check(inputs: Vec<&str>, expected: ASTValue) {
-        let env = create_global_environment();
+        let env = create_global_environment(&[]);
 
         let mut result: ASTValue = ASTValue::Number(0.0);
         for input in inputs {
             let mut lexer = Lexer::new(input);
             let ast = parse_statement(& mut lexer).ok().unwrap();
-            if let Some(value) = evaluate_node(&ast, env.clone()).ok().unwrap() {
+            if let Some(value) = evaluate_statement(&ast, env.clone()).ok().unwrap() {
                 result = value;
             }
         }
@@ -79,6 +264,10 @@ mod evaluator {
         assert_eq!(result, expected);
     }
 
+    fn ast_string(s: &str) -> ASTValue {
+        ASTValue::String(s.into())
+    }
+
     fn ast_array(elements: &[f64]) -> ASTValue {
         let mut results = vec![];
         for element in elements {
@@ -178,6 +367,182 @@ mod evaluator {
     }
 
     #[test]
+    fn index_assign1() {
+        let inputs = vec![
+            "let arr = [1, 2, 3, 4, 5];",
+            "arr[2] = 10;",
+            "arr"
+        ];
+        check(inputs, ast_array(&[1.0, 2.0, 10.0, 4.0, 5.0]));
+    }
+
+    #[test]
+    fn index_assign2() {
+        let inputs = vec![
+            "let m = [[1, 2], [3, 4]];",
+            "m[1][0] = m[0][1] * 5;",
+            "m[1]"
+        ];
+        check(inputs, ast_array(&[10.0, 4.0]));
+    }
+
+    #[test]
+    fn index_assign_invalid() {
+        //负数和非整数下标视为越界 不会改写其他元素
+        let err = runtime_error(vec!["let a = [1, 2, 3];", "a[-1] = 9"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: -1.0, len: 3 });
+        let err = runtime_error(vec!["let a = [1, 2, 3];", "a[0.5] = 9"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: 0.5, len: 3 });
+        let err = runtime_error(vec!["let a = [1, 2, 3];", "a[-1]"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: -1.0, len: 3 });
+        check(vec!["let a = [1, 2, 3];", "try { a[-1] = 9; } catch (e) {}", "a"], ast_array(&[1.0, 2.0, 3.0]));
+    }
+
+    #[test]
+    fn index_assign_copy_on_write() {
+        let inputs = vec![
+            "let a = [[1, 2], [3, 4]];",
+            "let b = a;",
+            "let row = a[0];",
+            "b[0][1] = 7;",
+            "[a[0][1], row[1], b[0][1]]"
+        ];
+        check(inputs, ast_array(&[2.0, 2.0, 7.0]));
+    }
+
+    #[test]
+    fn index_assign_loop() {
+        let inputs = vec![
+            "let v = [0, 0, 0, 0];",
+            "for i in range(0, 4) { v[i] = i ^ 2; }",
+            "v"
+        ];
+        check(inputs, ast_array(&[0.0, 1.0, 4.0, 9.0]));
+    }
+
+    #[test]
+    fn compound_assign() {
+        let inputs = vec![
+            "let x = 10;",
+            "x += 5;",
+            "x -= 3;",
+            "x *= 2;",
+            "x /= 4;",
+            "x ^= 2;",
+            "x %= 7;",
+            "x"
+        ];
+        check(inputs, ASTValue::Number(((10.0_f64 + 5.0 - 3.0) * 2.0 / 4.0).powi(2) % 7.0));
+
+        let inputs = vec![
+            "let s = \"a\";",
+            "for i in range(0, 3) { s += i; }",
+            "s"
+        ];
+        check(inputs, ast_string("a012"));
+    }
+
+    #[test]
+    fn increment() {
+        //x++、x--与x += 1、x -= 1相同
+        let inputs = vec![
+            "let i = 0;",
+            "let v = [5, 5];",
+            "while i < 3 { i++; v[1]--; }",
+            "i--",
+            "let a = 2;",
+            "[i, v[1], 1--2, a--a]"
+        ];
+        check(inputs, ast_array(&[2.0, 2.0, 3.0, 4.0]));
+
+        //x++没有值 不会结束语句块
+        let inputs = vec![
+            "let i = 0;",
+            "let total = 0;",
+            "while i < 3 { i++\n total += 10 }",
+            "[i, total]"
+        ];
+        check(inputs, ast_array(&[3.0, 30.0]));
+    }
+
+    #[test]
+    fn compound_index_assign() {
+        //下标表达式只求值一次
+        let inputs = vec![
+            "let m = [[1, 2], [3, 4]];",
+            "let calls = 0;",
+            "let row = () => { calls += 1; 1 };",
+            "m[row()][0] *= 10;",
+            "let r = { count: 1 };",
+            "r.count += 2;",
+            "[m[1][0], calls, r.count]"
+        ];
+        check(inputs, ast_array(&[30.0, 1.0, 3.0]));
+    }
+
+    #[test]
+    fn program() {
+        let source = "
+            let fib = (n) => {
+                if n < 2 {
+                    n
+                } else {
+                    fib(n - 1) + fib(n - 2)
+                }
+            }
+            let a = fib(10); let b = 2
+            a * b";
+        let env = create_global_environment(&[]);
+        let nodes = parse_program(&mut Lexer::new(source)).ok().unwrap();
+        assert_eq!(nodes.len(), 4);
+        let results: Vec<_> = nodes.iter().map(|node| evaluate_statement(node, env.clone()).ok().unwrap()).collect();
+        assert_eq!(results[3], Some(ASTValue::Number(110.0)));
+
+        assert!(parse_program(&mut Lexer::new("")).ok().unwrap().is_empty());
+        assert!(parse_program(&mut Lexer::new("let a = 1; let = 2")).is_err());
+    }
+
+    #[test]
+    fn script_args() {
+        let env = create_global_environment(&["data.csv".into(), "3".into()]);
+        let ast = parse_statement(&mut Lexer::new("[len(args), args[0], args[1]]")).ok().unwrap();
+        let value = evaluate_statement(&ast, env).ok().unwrap().unwrap();
+        assert_eq!(value, ASTValue::Array(vec![ASTValue::Number(2.0), ast_string("data.csv"), ast_string("3")].into()));
+
+        check(vec!["args"], ASTValue::Array(vec![].into()));
+    }
+
+    #[test]
+    fn environment_iter() {
+        let env = create_global_environment(&[]);
+        //每个内置的名字都有说明
+        for (name, _) in env.borrow().iter() {
+            assert!(primitive_help(name).is_some(), "no help for {}", name);
+        }
+
+        let ast = parse_statement(&mut Lexer::new("let point = {x: 1, y: \"a\"};")).ok().unwrap();
+        evaluate_statement(&ast, env.clone()).ok().unwrap();
+        let env = env.borrow();
+        let (_, point) = env.iter().find(|(name, _)| *name == "point").unwrap();
+        assert_eq!(point.type_name(), "map");
+        let types: Vec<_> = ["x", "y"].iter().map(|key| point.get_key(key).unwrap().type_name()).collect();
+        assert_eq!(types, ["number", "string"]);
+    }
+
+    #[test]
+    fn answer_history() {
+        let env = create_global_environment(&[]);
+        for input in ["3 + 4", "ans * 2", "ans1 + ans", "[ans, ans1, ans2]"] {
+            let ast = parse_statement(&mut Lexer::new(input)).ok().unwrap();
+            let value = evaluate_statement(&ast, env.clone()).ok().unwrap().unwrap();
+            regist_answer(env.clone(), value);
+        }
+        assert_eq!(env.borrow().get("ans", false), Some(ast_array(&[21.0, 14.0, 7.0])));
+        assert_eq!(env.borrow().get("ans2", false), Some(ASTValue::Number(14.0)));
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn numerical() {
         use std::f64::consts;
         //一元函数
@@ -215,6 +580,206 @@ mod evaluator {
         check(vec!["linespace(0, 1, 5)"], ast_array(&[0.0, 0.25, 0.5, 0.75, 1.0]));
     }
 
+    #[test]
+    fn string_concat() {
+        let inputs = vec![
+            "let name = \"world\";",
+            "\"hello, \" + name + \"! \" + 1.5 + \" \" + [1, \"a\", true]"
+        ];
+        check(inputs, ast_string("hello, world! 1.5 [1, \"a\", true]"));
+    }
+
+    #[test]
+    fn string_compare() {
+        check(vec!["[\"abc\" == \"abc\", \"abc\" != \"abd\", \"abc\" < \"abd\", \"b\" >= \"abc\"]"],
+            ASTValue::Array(vec![ASTValue::Boolean(true); 4].into()));
+    }
+
+    #[test]
+    fn string_index() {
+        check(vec!["let s = \"héllo\";", "s[1] + s[4]"], ast_string("éo"));
+        let err = runtime_error(vec!["\"abc\"[-1]"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: -1.0, len: 3 });
+        let err = runtime_error(vec!["\"abc\"[1.5]"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: 1.5, len: 3 });
+    }
+
+    #[test]
+    fn string_iterate() {
+        let inputs = vec![
+            "let r = \"\";",
+            "for c in \"abc\" { r = c + r; }",
+            "r"
+        ];
+        check(inputs, ast_string("cba"));
+    }
+
+    #[test]
+    fn string_primitive() {
+        check(vec!["len(\"héllo\")"], ASTValue::Number(5.0));
+        check(vec!["substr(\"hello\", 1, 3)"], ast_string("ell"));
+        check(vec!["substr(\"hello\", 3, 10)"], ast_string("lo"));
+        check(vec!["substr(\"hello\", 5, 1)"], ast_string(""));
+        let err = runtime_error(vec!["substr(\"héllo\", -3, 2)"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: -3.0, len: 5 });
+        let err = runtime_error(vec!["substr(\"héllo\", 1, -2)"]);
+        assert_eq!(err.kind, ErrorKind::InvalidArgument("count must be a non-negative integer"));
+        check(vec!["split(\"a,b,,c\", \",\")"], ASTValue::Array(vec![ast_string("a"), ast_string("b"), ast_string(""), ast_string("c")].into()));
+        check(vec!["join([\"a\", 1, true], \"-\")"], ast_string("a-1-true"));
+        check(vec!["upper(\"MiXed\") + lower(\"MiXed\")"], ast_string("MIXEDmixed"));
+        check(vec!["trim(\"  padded \\t\")"], ast_string("padded"));
+        check(vec!["replace(\"a-b-c\", \"-\", \"+\")"], ast_string("a+b+c"));
+        check(vec!["[find(\"héllo\", \"llo\"), find(\"hello\", \"z\")]"], ast_array(&[2.0, -1.0]));
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let inputs = vec![
+            "let x = 2;",
+            "let f = (x) => { [x, x ^ 2] };",
+            "\"x = {x}, f(x) = {f(x)}, {\"nested {x + 1}\"}{x > 1}\""
+        ];
+        check(inputs, ast_string("x = 2, f(x) = [2, 4], nested 3true"));
+    }
+
+    #[test]
+    fn string_interpolation_error() {
+        let mut lexer = Lexer::new("\"a = {1 +}\"");
+        let err = parse_statement(&mut lexer).err().unwrap();
+        assert_eq!(err.token, Token::End);
+
+        let mut lexer = Lexer::new("\"a = {1 2}\"");
+        let err = parse_statement(&mut lexer).err().unwrap();
+        assert_eq!(err.token, Token::Number("2".into()));
+        assert_eq!(err.span, Span(8, 9));
+    }
+
+    #[test]
+    fn map_literal() {
+        let inputs = vec![
+            "let m = {name: \"box\", \"full size\": [1, 2], nested: {x: 1}};",
+            "[m.name, m[\"full size\"][1], m.nested.x, len(m), len({})]"
+        ];
+        check(inputs, ASTValue::Array(vec![ast_string("box"), ASTValue::Number(2.0), ASTValue::Number(1.0), ASTValue::Number(3.0), ASTValue::Number(0.0)].into()));
+        check(vec!["{b: 2, a: 1}"],
+            ASTValue::Map(std::rc::Rc::new(vec![("a".into(), ASTValue::Number(1.0)), ("b".into(), ASTValue::Number(2.0))])));
+    }
+
+    #[test]
+    fn map_empty() {
+        //语句开头的{}是空语句块 赋值符号右侧、实参、括号中的{}是空映射
+        check(vec!["let f = (x) => { {} x };", "f(1)"], ASTValue::Number(1.0));
+        check(vec!["let m = {};", "m.a = {};", "[len(m), len(m.a), len({}), len(({}))]"], ast_array(&[1.0, 0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn map_assign() {
+        let inputs = vec![
+            "let m = {a: 1, inner: {b: [1, 2]}};",
+            "let alias = m;",
+            "m.a = 10;",
+            "m[\"c\"] = 3;",
+            "m.inner.b[1] = 20;",
+            "[m.a, m.c, m.inner.b[1], alias.a, alias.inner.b[1], has(alias, \"c\")]"
+        ];
+        check(inputs, ASTValue::Array(vec![
+            ASTValue::Number(10.0), ASTValue::Number(3.0), ASTValue::Number(20.0),
+            ASTValue::Number(1.0), ASTValue::Number(2.0), ASTValue::Boolean(false)].into()));
+    }
+
+    #[test]
+    fn map_iterate() {
+        let inputs = vec![
+            "let m = {z: 1, y: 2, x: 3};",
+            "let s = \"\";",
+            "for k in m { s = s + k + m[k]; }",
+            "s"
+        ];
+        check(inputs, ast_string("z1y2x3"));
+    }
+
+    #[test]
+    fn map_primitive() {
+        let inputs = vec![
+            "let stats = (arr) => { let s = 0; for x in arr { s = s + x; } {sum: s, mean: s / len(arr)} };",
+            "let r = remove(stats([1, 2, 3, 6]), \"sum\");",
+            "[keys(r), values(r), has(r, \"sum\"), has(r, \"mean\")]"
+        ];
+        check(inputs, ASTValue::Array(vec![
+            ASTValue::Array(vec![ast_string("mean")].into()), ast_array(&[3.0]),
+            ASTValue::Boolean(false), ASTValue::Boolean(true)].into()));
+    }
+
+    #[test]
+    fn display() {
+        let value = ASTValue::Array(vec![ast_string("a\"b"), ast_array(&[]), ast_array(&[1.0, 2.5])].into());
+        assert_eq!(value.to_string(), "[\"a\\\"b\", [], [1, 2.5]]");
+        assert_eq!(ast_string("plain").to_string(), "plain");
+
+        let value = ASTValue::Map(std::rc::Rc::new(vec![("a".into(), ast_string("x")), ("two words".into(), ast_array(&[1.0]))]));
+        assert_eq!(value.to_string(), "{a: \"x\", \"two words\": [1]}");
+    }
+
+    #[test]
+    fn number_literal() {
+        check(vec!["1_000 * 1e-3 + 0x10 + 0b11 + 2.5E1"], ASTValue::Number(1.0 + 16.0 + 3.0 + 25.0));
+    }
+
+    #[test]
+    fn precedence() {
+        //乘方右结合 且比单目负号结合更紧密
+        check(vec!["2 ^ 3 ^ 2"], ASTValue::Number(512.0));
+        check(vec!["-2 ^ 2"], ASTValue::Number(-4.0));
+        check(vec!["2 ^ -1"], ASTValue::Number(0.5));
+        check(vec!["-2 ^ -2"], ASTValue::Number(-0.25));
+        check(vec!["2 * 3 ^ 2"], ASTValue::Number(18.0));
+        check(vec!["(-2) ^ 2"], ASTValue::Number(4.0));
+        //单目负号比乘除结合更紧密
+        check(vec!["-3 * -2"], ASTValue::Number(6.0));
+        check(vec!["-3 + 5"], ASTValue::Number(2.0));
+        //同级运算左结合
+        check(vec!["10 - 4 - 3"], ASTValue::Number(3.0));
+        check(vec!["100 / 10 / 5"], ASTValue::Number(2.0));
+        check(vec!["7 % 3 * 2"], ASTValue::Number(2.0));
+        check(vec!["2 + 3 * 4"], ASTValue::Number(14.0));
+        //比较运算比相等运算结合更紧密
+        check(vec!["1 < 2 == 2 < 3"], ASTValue::Boolean(true));
+        check(vec!["1 > 2 != true"], ASTValue::Boolean(true));
+        check(vec!["1 + 2 < 4"], ASTValue::Boolean(true));
+        //逻辑运算
+        check(vec!["false || true"], ASTValue::Boolean(true));
+        check(vec!["true || false && false"], ASTValue::Boolean(true));
+        check(vec!["!(1 > 2) && 3 >= 3"], ASTValue::Boolean(true));
+        check(vec!["!true == false"], ASTValue::Boolean(true));
+    }
+
+    #[test]
+    fn short_circuit() {
+        let inputs = vec![
+            "let a = [3, 0, 5];",
+            "let i = 3;",
+            "i < length(a) && a[i] > 0"
+        ];
+        check(inputs, ASTValue::Boolean(false));
+
+        let inputs = vec![
+            "let calls = 0;",
+            "let touch = () => { calls = calls + 1; true };",
+            "let r = [true || touch(), false && touch(), false || touch(), true && touch()];",
+            "[calls, r]"
+        ];
+        let r = ASTValue::Array(vec![ASTValue::Boolean(true), ASTValue::Boolean(false), ASTValue::Boolean(true), ASTValue::Boolean(true)].into());
+        check(inputs, ASTValue::Array(vec![ASTValue::Number(2.0), r].into()));
+    }
+
+    #[test]
+    fn truthiness() {
+        check(vec!["[1 && true, 0 || false, 2 || x, 0 && x, !0, !3]"],
+            ASTValue::Array(vec![
+                ASTValue::Boolean(true), ASTValue::Boolean(false), ASTValue::Boolean(true),
+                ASTValue::Boolean(false), ASTValue::Boolean(true), ASTValue::Boolean(false)].into()));
+    }
+
     #[test]
     fn usrdef() {
         let inputs = vec![
@@ -242,6 +807,516 @@ mod evaluator {
         check(inputs, ASTValue::Number(5.0));
     }
 
+    #[test]
+    fn while_loop() {
+        let inputs = vec![
+            "let i = 0;",
+            "let s = 0;",
+            "while i < 5 { s = s + i; i = i + 1; }",
+            "s"
+        ];
+        check(inputs, ASTValue::Number(10.0));
+    }
+
+    #[test]
+    fn empty_block() {
+        //空语句块没有值
+        let inputs = vec![
+            "let i = 0;",
+            "let next = () => { i += 1; i < 3 };",
+            "while next() {}",
+            "for x in [1, 2] {}",
+            "try { error(\"ignored\") } catch (e) {}",
+            "i"
+        ];
+        check(inputs, ASTValue::Number(3.0));
+        check(vec!["let f = (x) => { if x {} else { 1 } };", "f(false)"], ASTValue::Number(1.0));
+    }
+
+    #[test]
+    fn for_loop() {
+        let inputs = vec![
+            "let s = 0;",
+            "for x in range(0, 10) { s = s + x; }",
+            "s"
+        ];
+        check(inputs, ASTValue::Number(45.0));
+    }
+
+    #[test]
+    fn for_loop_nested() {
+        let inputs = vec![
+            "let s = 0;",
+            "for row in [[1, 2], [3, 4]] { for x in row { s = s + x * x; } }",
+            "s"
+        ];
+        check(inputs, ASTValue::Number(30.0));
+    }
+
+    #[test]
+    fn loop_break() {
+        let inputs = vec![
+            "let s = 0;",
+            "for x in range(0, 100) { if x == 5 { break; } s = s + x; }",
+            "s"
+        ];
+        check(inputs, ASTValue::Number(10.0));
+    }
+
+    #[test]
+    fn loop_continue() {
+        let inputs = vec![
+            "let s = 0;",
+            "for x in range(0, 10) { if x % 2 == 0 { continue; } s = s + x; }",
+            "s"
+        ];
+        check(inputs, ASTValue::Number(25.0));
+    }
+
+    #[test]
+    fn early_return() {
+        let inputs = vec![
+            "let sign = (x) => { if x > 0 { return 1; } if x < 0 { return -1; } 0 }",
+            "[sign(3), sign(-2), sign(0)]"
+        ];
+        check(inputs, ast_array(&[1.0, -1.0, 0.0]));
+    }
+
+    #[test]
+    fn return_from_loop() {
+        let inputs = vec![
+            "let index_of = (arr, v) => { for i in range(0, length(arr)) { if arr[i] == v { return i; } } -1 }",
+            "[index_of([4, 5, 6], 6), index_of([4, 5, 6], 7)]"
+        ];
+        check(inputs, ast_array(&[2.0, -1.0]));
+    }
+
+    #[test]
+    fn closure_counter() {
+        let inputs = vec![
+            "let make_counter = () => { let n = 0; () => { n = n + 1; n } };",
+            "let c1 = make_counter();",
+            "let c2 = make_counter();",
+            "c1();",
+            "c1();",
+            "c2();",
+            "[c1(), c2()]"
+        ];
+        check(inputs, ast_array(&[3.0, 2.0]));
+    }
+
+    #[test]
+    fn closure_capture_lambda() {
+        let inputs = vec![
+            "let compose = (f, g) => { (x) => { f(g(x)) } };",
+            "let inc = (x) => { x + 1 };",
+            "let square = (x) => { x ^ 2 };",
+            "compose(inc, square)(3)"
+        ];
+        check(inputs, ASTValue::Number(10.0));
+    }
+
+    #[test]
+    fn closure_lexical_scope() {
+        let inputs = vec![
+            "let x = 1;",
+            "let f = () => { x };",
+            "let g = () => { let x = 2; f() };",
+            "x = 5;",
+            "g()"
+        ];
+        check(inputs, ASTValue::Number(5.0));
+    }
+
+    #[test]
+    fn named_lambda() {
+        let env = create_global_environment(&[]);
+        let mut lexer = Lexer::new("let fact = (n) => { if n == 1 { 1 } else { n * fact(n - 1) } }");
+        let ast = parse_statement(&mut lexer).ok().unwrap();
+        let value = evaluate_statement(&ast, env).ok().unwrap().unwrap();
+        assert_eq!(format!("{:?}", value), "fn-fact");
+
+        let mut lexer = Lexer::new("(x) => { x }");
+        let ast = parse_statement(&mut lexer).ok().unwrap();
+        let value = evaluate_statement(&ast, create_global_environment(&[])).ok().unwrap().unwrap();
+        assert_eq!(format!("{:?}", value), "fn-anonymous");
+    }
+
+    #[test]
+    fn nested_recursion() {
+        let inputs = vec![
+            "let f = (n) => { let fact = (k) => { if k <= 1 { 1 } else { k * fact(k - 1) } }; fact(n) }",
+            "f(6)"
+        ];
+        check(inputs, ASTValue::Number(720.0));
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let inputs = vec![
+            "let parity = (n) => { let even = (k) => { if k == 0 { true } else { odd(k - 1) } }; let odd = (k) => { if k == 0 { false } else { even(k - 1) } }; [even(n), odd(n)] }",
+            "parity(7)"
+        ];
+        check(inputs, ASTValue::Array(vec![ASTValue::Boolean(false), ASTValue::Boolean(true)].into()));
+    }
+
+    #[test]
+    fn local_lambda_release() {
+        //调用结束后函数内定义的函数和函数的环境不再互相引用 不会泄漏
+        let env = create_global_environment(&[]);
+        let inputs = [
+            "let f = (x) => { let big = range(0, 10); let h = (y) => { y + len(big) }; h(x) };",
+            "let parity = (n) => { let even = (k) => { if k == 0 { true } else { odd(k - 1) } }; let odd = (k) => { if k == 0 { false } else { even(k - 1) } }; [even(n), odd(n)] };",
+            "let make_counter = () => { let n = 0; let inc = () => { n += 1; n }; inc };",
+        ];
+        for input in inputs {
+            let ast = parse_statement(&mut Lexer::new(input)).ok().unwrap();
+            evaluate_statement(&ast, env.clone()).ok().unwrap();
+        }
+        let count = Rc::strong_count(&env);
+        for input in ["f(1)", "parity(3)", "f(2) + 1"] {
+            let ast = parse_statement(&mut Lexer::new(input)).ok().unwrap();
+            evaluate_statement(&ast, env.clone()).ok().unwrap();
+            assert_eq!(Rc::strong_count(&env), count);
+        }
+
+        //逃逸出去的函数仍然持有环境
+        let ast = parse_statement(&mut Lexer::new("let c = make_counter();")).ok().unwrap();
+        evaluate_statement(&ast, env.clone()).ok().unwrap();
+        assert_eq!(Rc::strong_count(&env), count + 1);
+        let ast = parse_statement(&mut Lexer::new("c() + c()")).ok().unwrap();
+        assert_eq!(evaluate_statement(&ast, env.clone()).ok().unwrap(), Some(ASTValue::Number(3.0)));
+    }
+
+    #[test]
+    fn tail_call_cond() {
+        let inputs = vec![
+            "let sum = (n, acc) => { if n == 0 { acc } else { sum(n - 1, acc + n) } }",
+            "sum(100000, 0)"
+        ];
+        check(inputs, ASTValue::Number(5000050000.0));
+    }
+
+    #[test]
+    fn tail_call_return() {
+        let inputs = vec![
+            "let count = (n) => { if n > 0 { return count(n - 1); } 0 }",
+            "count(100000)"
+        ];
+        check(inputs, ASTValue::Number(0.0));
+    }
+
+    #[test]
+    fn tail_call_mutual() {
+        let inputs = vec![
+            "let even = (n) => { if n == 0 { true } else { odd(n - 1) } }",
+            "let odd = (n) => { if n == 0 { false } else { even(n - 1) } }",
+            "even(100001)"
+        ];
+        check(inputs, ASTValue::Boolean(false));
+    }
+
+    #[test]
+    fn call_depth_limit() {
+        let env = create_global_environment(&[]);
+        let evaluate = |input: &str| {
+            let mut lexer = Lexer::new(input);
+            let ast = parse_statement(&mut lexer).ok().unwrap();
+            evaluate_statement(&ast, env.clone())
+        };
+
+        evaluate("max_call_depth(50);").unwrap();
+        evaluate("let depth = (n) => { if n == 0 { 0 } else { 1 + depth(n - 1) } };").unwrap();
+        let err = evaluate("depth(100)").err().unwrap();
+        assert_eq!(err.kind, ErrorKind::StackOverflow { name: Some("depth".into()), depth: 50 });
+        assert_eq!(err.to_string(), "stack overflow: maximum call depth 50 exceeded in depth");
+
+        //出错后调用深度计数被正确恢复
+        assert_eq!(evaluate("depth(40)").unwrap(), Some(ASTValue::Number(40.0)));
+        assert_eq!(evaluate("max_call_depth()").unwrap(), Some(ASTValue::Number(50.0)));
+    }
+
+    #[test]
+    fn stack_limit() {
+        //默认的最大调用深度以及很大的最大调用深度在默认大小的线程栈上都只报告错误 不会使进程崩溃
+        let result = std::thread::Builder::new()
+            .stack_size(DEFAULT_STACK_SIZE)
+            .spawn(|| {
+                let env = create_global_environment(&[]);
+                let evaluate = |input: &str| {
+                    let mut lexer = Lexer::new(input);
+                    let ast = parse_statement(&mut lexer).ok().unwrap();
+                    evaluate_statement(&ast, env.clone())
+                };
+
+                evaluate("let f = (n) => { if n == 0 { 0 } else { 1 + f(n - 1) } };").unwrap();
+                let err = evaluate("f(2000)").err().unwrap();
+                assert_eq!(err.kind.name(), "stack_overflow");
+
+                evaluate("max_call_depth(1000000);").unwrap();
+                let err = evaluate("f(100000)").err().unwrap();
+                assert_eq!(err.kind.name(), "stack_overflow");
+
+                //出错后仍然可以正常调用
+                assert_eq!(evaluate("f(10)").unwrap(), Some(ASTValue::Number(10.0)));
+            })
+            .unwrap()
+            .join();
+        assert!(result.is_ok());
+    }
+
+    //依次执行各条语句 返回最后一条语句的错误
+    fn runtime_error(inputs: Vec<&str>) -> RuntimeError {
+        let env = create_global_environment(&[]);
+        let (last, init) = inputs.split_last().unwrap();
+        for input in init {
+            let ast = parse_statement(&mut Lexer::new(input)).ok().unwrap();
+            evaluate_statement(&ast, env.clone()).ok().unwrap();
+        }
+        let ast = parse_statement(&mut Lexer::new(last)).ok().unwrap();
+        evaluate_statement(&ast, env).err().unwrap()
+    }
+
+    #[test]
+    fn error_kind() {
+        let err = runtime_error(vec!["1 + true"]);
+        assert_eq!(err.kind, ErrorKind::TypeMismatch { op: "+".into(), types: vec!["number", "boolean"] });
+        assert_eq!(err.span, Some(Span(2, 3)));
+        assert_eq!(err.to_string(), "`+` can not be applied to number and boolean");
+
+        let err = runtime_error(vec!["-\"a\""]);
+        assert_eq!(err.kind, ErrorKind::TypeMismatch { op: "-".into(), types: vec!["string"] });
+        assert_eq!(err.span, Some(Span(0, 1)));
+
+        let err = runtime_error(vec!["1 + x"]);
+        assert_eq!(err.kind, ErrorKind::UndefinedName("x".into()));
+        assert_eq!(err.span, Some(Span(4, 5)));
+
+        let err = runtime_error(vec!["let a = 1;", "let a = 2"]);
+        assert_eq!(err.kind, ErrorKind::Redefinition("a".into()));
+        assert_eq!(err.span, Some(Span(4, 5)));
+
+        let err = runtime_error(vec!["[1, 2][5]"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: 5.0, len: 2 });
+        assert_eq!(err.span, Some(Span(0, 9)));
+
+        let err = runtime_error(vec!["let m = {a: 1};", "m.b"]);
+        assert_eq!(err.kind, ErrorKind::KeyNotFound("b".into()));
+        assert_eq!(err.span, Some(Span(0, 3)));
+
+        let err = runtime_error(vec!["3(1)"]);
+        assert_eq!(err.kind, ErrorKind::NotCallable("number"));
+        assert_eq!(err.span, Some(Span(0, 4)));
+
+        let err = runtime_error(vec!["upper(1)"]);
+        assert_eq!(err.kind, ErrorKind::WrongType { expected: "string", found: "number" });
+        assert_eq!(err.span, Some(Span(0, 8)));
+
+        let err = runtime_error(vec!["break"]);
+        assert_eq!(err.kind, ErrorKind::OutsideLoop("break"));
+        assert_eq!(err.span, Some(Span(0, 5)));
+        assert_eq!(err.to_string(), "break outside of loop");
+
+        //函数体中游离在循环外的continue定位到continue本身
+        let err = runtime_error(vec!["let f = () => { continue };", "f()"]);
+        assert_eq!(err.kind, ErrorKind::OutsideLoop("continue"));
+        assert_eq!(err.span, Some(Span(16, 24)));
+
+        let err = runtime_error(vec!["linespace(0, 1, 1)"]);
+        assert_eq!(err.kind, ErrorKind::InvalidArgument("number of steps cannot be less than 2"));
+        assert_eq!(err.kind.name(), "invalid_argument");
+
+        let err = runtime_error(vec!["max_call_depth(1, 2)"]);
+        assert_eq!(err.kind, ErrorKind::Arity { name: Some("max_call_depth".into()), expected: 1, found: 2 });
+    }
+
+    #[test]
+    fn error_assign() {
+        //赋值出错时定位到左值
+        let err = runtime_error(vec!["k = 1"]);
+        assert_eq!(err.kind, ErrorKind::UndefinedName("k".into()));
+        assert_eq!(err.span, Some(Span(0, 1)));
+
+        let err = runtime_error(vec!["n += 1"]);
+        assert_eq!(err.kind, ErrorKind::UndefinedName("n".into()));
+        assert_eq!(err.span, Some(Span(0, 1)));
+
+        let err = runtime_error(vec!["let s = \"a\";", "s -= 1"]);
+        assert_eq!(err.kind, ErrorKind::TypeMismatch { op: "-".into(), types: vec!["string", "number"] });
+        assert_eq!(err.span, Some(Span(0, 1)));
+
+        let err = runtime_error(vec!["let v = [1, 2, 3];", "v[3] = 1"]);
+        assert_eq!(err.kind, ErrorKind::IndexOutOfBounds { index: 3.0, len: 3 });
+        assert_eq!(err.span, Some(Span(0, 4)));
+
+        let err = runtime_error(vec!["let m = {a: 1};", "m.b.c = 2"]);
+        assert_eq!(err.kind, ErrorKind::KeyNotFound("b".into()));
+        assert_eq!(err.span, Some(Span(0, 5)));
+
+        let err = runtime_error(vec!["let x = 1;", "x[0] = 2"]);
+        assert_eq!(err.kind, ErrorKind::TypeMismatch { op: "[]".into(), types: vec!["number", "number"] });
+        assert_eq!(err.span, Some(Span(0, 4)));
+
+        //索引链的根变量未定义时定位到根变量
+        let err = runtime_error(vec!["w[1][2] = 3"]);
+        assert_eq!(err.kind, ErrorKind::UndefinedName("w".into()));
+        assert_eq!(err.span, Some(Span(0, 1)));
+
+        //下标表达式中的错误仍然定位到下标内部
+        let err = runtime_error(vec!["let v = [1];", "v[y] = 1"]);
+        assert_eq!(err.kind, ErrorKind::UndefinedName("y".into()));
+        assert_eq!(err.span, Some(Span(2, 3)));
+    }
+
+    #[test]
+    fn error_arity() {
+        //原始函数的函数名由PrimitiveFun补上
+        let err = runtime_error(vec!["sqrt(1, 2)"]);
+        assert_eq!(err.kind, ErrorKind::Arity { name: Some("sqrt".into()), expected: 1, found: 2 });
+        assert_eq!(err.span, Some(Span(0, 10)));
+        assert_eq!(err.to_string(), "`sqrt` expects 1 argument but got 2");
+
+        let err = runtime_error(vec!["let f = (x, y) => { x + y };", "f(1)"]);
+        assert_eq!(err.kind, ErrorKind::Arity { name: Some("f".into()), expected: 2, found: 1 });
+
+        //map中调用的匿名函数不会被标记为map
+        let err = runtime_error(vec!["map([1, 2], () => { 1 })"]);
+        assert_eq!(err.kind, ErrorKind::Arity { name: Some("<lambda>".into()), expected: 0, found: 1 });
+
+        //尾调用出错时定位到尾调用表达式
+        let err = runtime_error(vec!["let g = (x) => { x };", "let f = () => { g() };", "f()"]);
+        assert_eq!(err.kind, ErrorKind::Arity { name: Some("g".into()), expected: 1, found: 0 });
+        assert_eq!(err.span, Some(Span(16, 19)));
+    }
+
+    #[test]
+    fn error_span() {
+        //函数体中的错误定位到函数体内部 而不是调用处
+        let err = runtime_error(vec!["let f = (x) => { x * \"a\" };", "f(1)"]);
+        assert_eq!(err.span, Some(Span(19, 20)));
+
+        let source = "let a = [1, 2];\n  a[2] + 1";
+        let nodes = parse_program(&mut Lexer::new(source)).ok().unwrap();
+        let env = create_global_environment(&[]);
+        evaluate_statement(&nodes[0], env.clone()).ok().unwrap();
+        let err = evaluate_statement(&nodes[1], env).err().unwrap();
+        assert_eq!(err.render(source), "\
+evaluate error: index 2 out of bounds for length 2
+ --> 2:3
+  |
+2 |   a[2] + 1
+  |   ^^^^");
+    }
+
+    #[test]
+    fn error_trace() {
+        let frame = |name: &str, span: Option<Span>| Frame { name: name.into(), span };
+
+        //原始函数调用的匿名函数没有调用位置
+        let err = runtime_error(vec![
+            "let inv = (x) => { 1 / x + y };",
+            "let apply = (arr) => { map(arr, (v) => { inv(v) }) };",
+            "apply([1])",
+        ]);
+        assert_eq!(err.trace, vec![
+            frame("inv", Some(Span(41, 47))),
+            frame("<lambda>", None),
+            frame("map", Some(Span(23, 50))),
+            frame("apply", Some(Span(0, 10))),
+        ]);
+
+        //尾调用的外层函数已经离开了调用栈
+        let err = runtime_error(vec!["let f = (n) => { if n == 0 { n + true } else { f(n - 1) } };", "f(2)"]);
+        assert_eq!(err.trace, vec![frame("f", Some(Span(47, 55))), frame("f", Some(Span(0, 4)))]);
+
+        let source = "max_call_depth(20);\nlet d = (n) => { 1 + d(n - 1) };\nd(5)";
+        let nodes = parse_program(&mut Lexer::new(source)).ok().unwrap();
+        let env = create_global_environment(&[]);
+        evaluate_statement(&nodes[0], env.clone()).ok().unwrap();
+        evaluate_statement(&nodes[1], env.clone()).ok().unwrap();
+        let err = evaluate_statement(&nodes[2], env).err().unwrap();
+        assert_eq!(err.trace.len(), 21);
+        assert_eq!(err.render(source), "\
+evaluate error: stack overflow: maximum call depth 20 exceeded in d
+ --> 2:22
+  |
+2 | let d = (n) => { 1 + d(n - 1) };
+  |                      ^^^^^^^^
+call stack (most recent call first):
+  d called at 2:22
+  ... repeated 19 more times
+  d called at 3:1");
+    }
+
+    #[test]
+    fn try_catch() {
+        let inputs = vec![
+            "let safe_div = (a, b) => { if b == 0 { error(\"division by zero\") } a / b };",
+            "try { safe_div(1, 0) } catch (e) { e.kind + \": \" + e.message }",
+        ];
+        check(inputs, ast_string("error: division by zero"));
+
+        //没有出错时结果为try语句块的值
+        check(vec!["try { 1 + 2 } catch (e) { 0 }"], ASTValue::Number(3.0));
+
+        //运行时错误的种类和信息
+        let inputs = vec![
+            "let err = try { [1, 2][5] } catch (e) { e };",
+            "[err.kind, err.message]",
+        ];
+        check(inputs, ASTValue::Array(vec![ast_string("index_out_of_bounds"), ast_string("index 5 out of bounds for length 2")].into()));
+
+        //return中的尾调用出错时同样被捕获
+        let inputs = vec![
+            "let g = (x) => { x + true };",
+            "let f = (x) => { try { return g(x) } catch (e) { -1 } };",
+            "f(1)",
+        ];
+        check(inputs, ASTValue::Number(-1.0));
+
+        //break、continue和return穿过try照常生效
+        let inputs = vec![
+            "let n = 0;",
+            "for i in range(0, 5) { try { if i == 3 { break } n += 1; } catch (e) { n = -100; } };",
+            "let f = () => { try { return 7 } catch (e) { 0 } };",
+            "n + f()",
+        ];
+        check(inputs, ASTValue::Number(10.0));
+    }
+
+    #[test]
+    fn throw() {
+        let err = runtime_error(vec!["throw \"bad input\""]);
+        assert_eq!(err.kind, ErrorKind::User { kind: "error".into(), message: "bad input".into() });
+        assert_eq!(err.span, Some(Span(0, 5)));
+
+        //重新抛出时保留原来的种类
+        let err = runtime_error(vec!["try { 1 + undefined } catch (e) { throw e }"]);
+        assert_eq!(err.kind.name(), "undefined_name");
+        assert_eq!(err.to_string(), "undefined variable `undefined`");
+
+        let err = runtime_error(vec!["error(\"stop\")"]);
+        assert_eq!(err.kind, ErrorKind::User { kind: "error".into(), message: "stop".into() });
+
+        let err = runtime_error(vec!["throw 1"]);
+        assert_eq!(err.kind, ErrorKind::WrongType { expected: "string or map", found: "number" });
+
+        //映射中的kind和message都必须是字符串
+        let err = runtime_error(vec!["throw {message: \"m\", kind: 3}"]);
+        assert_eq!(err.kind, ErrorKind::WrongType { expected: "string", found: "number" });
+        let err = runtime_error(vec!["throw {message: true}"]);
+        assert_eq!(err.kind, ErrorKind::WrongType { expected: "string", found: "boolean" });
+        let err = runtime_error(vec!["throw {kind: \"k\"}"]);
+        assert_eq!(err.kind, ErrorKind::KeyNotFound("message".into()));
+
+        //catch中抛出的错误不会被同一个try捕获
+        let inputs = vec![
+            "let r = try { try { error(\"inner\") } catch (e) { throw e.message + \" again\" } } catch (e) { e.message };",
+            "r",
+        ];
+        check(inputs, ast_string("inner again"));
+    }
+
     #[test]
     fn higher_lambda1() {
         let inputs = vec![
@@ -268,4 +1343,108 @@ mod evaluator {
         ];
         check(inputs, ASTValue::Number(6.0));
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod parser {
+    use crate::gramma::lexer::{Lexer, Span};
+    use crate::gramma::parser::{parse_program, render_errors, ParseError};
+    use crate::gramma::diagnostic::line_col;
+
+    fn parse_error(source: &str) -> ParseError {
+        parse_program(&mut Lexer::new(source)).err().unwrap().remove(0)
+    }
+
+    #[test]
+    fn expected_tokens() {
+        assert_eq!(parse_error("(1 + 2;").to_string(), "expected `)` but found `;`");
+        assert_eq!(parse_error("f(1, 2").to_string(), "expected `,` or `)` but found end of input");
+        assert_eq!(parse_error("{a: 1 b: 2}").to_string(), "expected `,` or `}` but found `b`");
+        assert_eq!(parse_error("1 + *").to_string(), "expected expression but found `*`");
+        assert_eq!(parse_error("for 1 in a {}").to_string(), "expected identifier but found `1`");
+        assert_eq!(parse_error("3 = 4").to_string(), "unexpected `=`");
+        assert_eq!(parse_error("1 + $").to_string(), "expected expression but found illegal character `$`");
+        assert_eq!(parse_error("let a = [1]; a[]").to_string(), "expected expression but found `]`");
+        //x++只能单独作为一句语句
+        assert_eq!(parse_error("let z = 5; z++ + 1").span, Span(12, 14));
+        assert_eq!(parse_error("let x = 1; let y = x++;").to_string(), "unexpected `++`");
+        assert_eq!(parse_error("let x = [1]; x[0]-- * 2").to_string(), "unexpected `--`");
+    }
+
+    #[test]
+    fn render() {
+        let source = "let a = 1;\nlet b = (a +\n   2;";
+        let err = parse_error(source);
+        assert_eq!(err.span, Span(28, 29));
+        assert_eq!(line_col(source, err.span.0), (3, 5));
+        assert_eq!(err.render(source), "\
+parse error: expected `)` but found `;`
+ --> 3:5
+  |
+3 |    2;
+  |     ^");
+
+        //输入提前结束时标注在最后一个符号之后
+        assert_eq!(parse_error("while x {").render("while x {"), "\
+parse error: expected `}` but found end of input
+ --> 1:10
+  |
+1 | while x {
+  |          ^");
+
+        //字符串中错误的转义字符标注在\q处 而不是开头的引号
+        let source = "\"tab\\q\"";
+        assert_eq!(parse_error(source).render(source), "\
+parse error: expected expression but found illegal character `q`
+ --> 1:5
+  |
+1 | \"tab\\q\"
+  |     ^^");
+
+        //空的插值表达式标注在{}之间 而不是源码开头
+        let source = "let x = 1; \"abc {}\"";
+        assert_eq!(parse_error(source).render(source), "\
+parse error: expected expression but found end of input
+ --> 1:18
+  |
+1 | let x = 1; \"abc {}\"
+  |                  ^");
+    }
+
+    #[test]
+    fn recovery() {
+        //每条出错的语句报告一次 之后的语句继续解析
+        let source = "let a = [1, 2 3];\nlet f = (x) => { x + ; 1 };\nif a { 1 } else { ) }\nlet b = 4;\n}\nb *";
+        let errors = parse_program(&mut Lexer::new(source)).err().unwrap();
+        let spans = errors.iter().map(|err| err.span).collect::<Vec<_>>();
+        assert_eq!(spans, vec![Span(14, 15), Span(39, 40), Span(64, 65), Span(79, 80), Span(83, 84)]);
+        assert_eq!(errors[1].to_string(), "expected expression but found `;`");
+        assert!(render_errors(&errors, source).ends_with("\n\n5 parse errors"));
+
+        //缺少右括号时在;处结束出错的语句 不会吞掉后面的语句
+        let source = "let a = sqrt(1 + 2;\nlet b = [1, 2 3];\nlet c = 4 +;";
+        let errors = parse_program(&mut Lexer::new(source)).err().unwrap();
+        let spans = errors.iter().map(|err| err.span).collect::<Vec<_>>();
+        assert_eq!(spans, vec![Span(18, 19), Span(34, 35), Span(49, 50)]);
+
+        //{}中未闭合的括号 跳到}处再继续
+        let source = "let f = () => { g(1; 2 };\nlet c = 4 +;";
+        let errors = parse_program(&mut Lexer::new(source)).err().unwrap();
+        let spans = errors.iter().map(|err| err.span).collect::<Vec<_>>();
+        assert_eq!(spans, vec![Span(19, 20), Span(37, 38)]);
+
+        //函数体中的每条出错的语句同样各报告一次
+        let source = "let f = (x) => {\n let y = x +;\n let z = * 2;\n y\n}\nf(1 +)";
+        let errors = parse_program(&mut Lexer::new(source)).err().unwrap();
+        let spans = errors.iter().map(|err| err.span).collect::<Vec<_>>();
+        assert_eq!(spans, vec![Span(29, 30), Span(40, 41), Span(55, 56)]);
+
+        //输入在语句块中提前结束时只报告一次
+        let errors = parse_program(&mut Lexer::new("let f = () => { x +")).err().unwrap();
+        assert_eq!(errors.len(), 1);
+
+        //只有一个错误时不注明个数
+        let errors = parse_program(&mut Lexer::new("let a = 1 +;\na + 1")).err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(render_errors(&errors, "let a = 1 +;\na + 1"), errors[0].render("let a = 1 +;\na + 1"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 //符号集
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Token {
@@ -7,6 +9,18 @@ pub enum Token {
     If,
     ElseIf,
     Else,
+    //循环
+    While,
+    For,
+    In,
+    //控制流跳转
+    Break,
+    Continue,
+    Return,
+    //错误处理
+    Try,
+    Catch,
+    Throw,
     //小括号
     LeftParen,
     RightParen,
@@ -20,10 +34,16 @@ pub enum Token {
     Comma,
     //赋值
     Assign,
+    //复合赋值 += -= *= /= ^= %=
+    CompoundAssign(Op),
+    //自增++ 自减--
+    Increment(Op),
     //lambda函数定义
     Arrow,
     //冒号
     Colon,
+    //点(访问映射的键)
+    Dot,
     //分号
     SemiColon,
     //运算符
@@ -32,6 +52,10 @@ pub enum Token {
     Boolean(bool),
     //数字字面量
     Number(String),
+    //字符串字面量(已处理转义字符)
+    String(String),
+    //带有{expr}插值的字符串字面量
+    Template(Vec<TemplatePart>),
     //值变量或者函数变量的标识符
     Symbol(String),
     //非法符号
@@ -40,6 +64,15 @@ pub enum Token {
     End,
 }
 
+//插值字符串的组成部分
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TemplatePart {
+    //普通文本(已处理转义字符)
+    Text(String),
+    //嵌入表达式的源码 以及源码在整个输入中的起始位置
+    Expr(String, usize),
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 //运算符集
 pub enum Op {
@@ -60,17 +93,114 @@ pub enum Op {
     Or,
 }
 
+//二元运算符的结合性
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Assoc {
+    //左结合 a - b - c = (a - b) - c
+    Left,
+    //右结合 a ^ b ^ c = a ^ (b ^ c)
+    Right,
+}
+
+//运算符优先级表(数字越大结合越紧密)
+//  ||                  0  左结合
+//  &&                  1  左结合
+//  == !=               2  左结合
+//  < > <= >=           3  左结合
+//  + -                 4  左结合
+//  * / %               5  左结合
+//  单目 + - !          6
+//  ^                   7  右结合
+//单目运算比乘方松 比乘除紧 因此-2^2 = -(2^2) 2^-1 = 0.5 -a*b = (-a)*b
 impl Op {
+    //单目运算优先级
+    pub const UNITARY_PRIORITY: i32 = 6;
+
     //二元算符优先级计算
     pub fn priority(&self) -> i32 {
         match *self {
             Op::Or => 0,
             Op::And => 1,
-            Op::Lt | Op::Gt | Op::Lte | Op::Gte => 2,
-            Op::Eq | Op::Neq => 3,
+            Op::Eq | Op::Neq => 2,
+            Op::Lt | Op::Gt | Op::Lte | Op::Gte => 3,
             Op::Add | Op::Sub => 4,
-            Op::Mul | Op::Div | Op::Pow | Op::Mod => 5,
+            Op::Mul | Op::Div | Op::Mod => 5,
+            Op::Pow => 7,
             _ => -1,
         }
     }
-}
\ No newline at end of file
+
+    //二元算符结合性
+    pub fn assoc(&self) -> Assoc {
+        match *self {
+            Op::Pow => Assoc::Right,
+            _ => Assoc::Left,
+        }
+    }
+}
+
+//符号在源码中的写法 用于错误信息
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Let => write!(f, "let"),
+            Token::If => write!(f, "if"),
+            Token::ElseIf => write!(f, "elseif"),
+            Token::Else => write!(f, "else"),
+            Token::While => write!(f, "while"),
+            Token::For => write!(f, "for"),
+            Token::In => write!(f, "in"),
+            Token::Break => write!(f, "break"),
+            Token::Continue => write!(f, "continue"),
+            Token::Return => write!(f, "return"),
+            Token::Try => write!(f, "try"),
+            Token::Catch => write!(f, "catch"),
+            Token::Throw => write!(f, "throw"),
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+            Token::LeftBracket => write!(f, "["),
+            Token::RightBracket => write!(f, "]"),
+            Token::LeftBrace => write!(f, "{{"),
+            Token::RightBrace => write!(f, "}}"),
+            Token::Comma => write!(f, ","),
+            Token::Assign => write!(f, "="),
+            Token::CompoundAssign(op) => write!(f, "{}=", op),
+            Token::Increment(op) => write!(f, "{}{}", op, op),
+            Token::Arrow => write!(f, "=>"),
+            Token::Colon => write!(f, ":"),
+            Token::Dot => write!(f, "."),
+            Token::SemiColon => write!(f, ";"),
+            Token::Operator(op) => write!(f, "{}", op),
+            Token::Boolean(value) => write!(f, "{}", value),
+            Token::Number(num) => write!(f, "{}", num),
+            Token::String(s) => write!(f, "{:?}", s),
+            Token::Template(_) => write!(f, "string"),
+            Token::Symbol(name) => write!(f, "{}", name),
+            Token::Illegal(c) => write!(f, "{}", c),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Pow => "^",
+            Op::Mod => "%",
+            Op::Eq => "==",
+            Op::Neq => "!=",
+            Op::Lt => "<",
+            Op::Gt => ">",
+            Op::Lte => "<=",
+            Op::Gte => ">=",
+            Op::Not => "!",
+            Op::And => "&&",
+            Op::Or => "||",
+        };
+        write!(f, "{}", symbol)
+    }
+}
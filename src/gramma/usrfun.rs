@@ -2,7 +2,8 @@ use std::rc::Rc;
 use std::cell::RefCell;
 use crate::gramma::environment::Environment;
 use crate::gramma::ast::{ Callable, ASTNode, ASTValue };
-use crate::gramma::evaluator::evaluate_node;
+use crate::gramma::error::{ErrorKind, RuntimeError};
+use crate::gramma::evaluator::{evaluate_tail, catch_return, CallDepthGuard, Signal};
 
 //用户在程序执行时自定义的函数
 pub struct UsrDefFun {
@@ -13,29 +14,44 @@ pub struct UsrDefFun {
     //函数体
     //用Rc而非Box的原因是实际存储他的容器也是用的Rc
     pub body: Rc<ASTNode>,
+    //定义函数时所在的环境(闭包捕获的环境)
+    pub env: Rc<RefCell<Environment>>,
 }
 
 impl Callable for UsrDefFun {
     fn name(&self) -> Option<&str> {
-        match &self.name {
-            Some(x) => Some(x),
-            None => None,
-        }
+        self.name.as_deref()
+    }
+
+    fn call(&self, args: &[ASTValue], env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, RuntimeError> {
+        //尾调用在catch_return中循环执行 不增加调用深度
+        //函数体中的return和尾调用在此处被截获
+        let result = CallDepthGuard::enter(self.name()).and_then(|_guard| catch_return(self.call_tail(args, env.clone()), env));
+        //出错时把本函数加入调用栈 调用位置由调用方补上
+        result.map_err(|err| err.in_function(self.name()))
     }
 
-    fn call(&self, args: &[ASTValue], env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, String> {
+    fn call_tail(&self, args: &[ASTValue], _env: Rc<RefCell<Environment>>) -> Result<Option<ASTValue>, Signal> {
         //校验实参和形参数量是否一致
         if self.params.len() != args.len() {
-            raise!("wrong number of arguments")
+            raise!(ErrorKind::Arity {
+                name: Some(self.name.as_deref().unwrap_or("<lambda>").to_string()),
+                expected: self.params.len(),
+                found: args.len(),
+            })
         }
 
-        //创建函数执行时所在的新环境
-        let sub_env = Rc::new(RefCell::new(Environment::new(Some(env))));
+        //创建函数执行时所在的新环境 其父环境是函数定义时的环境而非调用者的环境
+        let sub_env = Rc::new(RefCell::new(Environment::new(Some(self.env.clone()))));
         for (param, arg) in self.params.iter().zip(args) {
             sub_env.borrow_mut().regist(param, arg.clone());
         }
 
-        //执行函数
-        evaluate_node(&self.body.clone(), sub_env.clone())
+        //执行函数 函数体处于尾部位置
+        evaluate_tail(&self.body, sub_env)
     }
-}
\ No newline at end of file
+
+    fn env(&self) -> Option<&Rc<RefCell<Environment>>> {
+        Some(&self.env)
+    }
+}
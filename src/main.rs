@@ -1,67 +1,131 @@
 mod gramma;
+mod editor;
+mod repl;
 use gramma::lexer::Lexer;
-use gramma::ast::ASTValue;
-use gramma::parser::parse_statement;
-use gramma::primitive::create_global_environment;
-use gramma::evaluator::evaluate_node;
+use gramma::parser::{parse_program, render_errors};
+use gramma::environment::Environment;
+use gramma::primitive::{create_global_environment, regist_answer};
+use gramma::evaluator::{evaluate_statement, set_stack_size};
+use repl::repl;
 
 use std::io;
 use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::thread;
+use std::process::ExitCode;
 
-fn main() -> io::Result<()> {
-    //创建父环境
-    let env = create_global_environment();
+//解释器线程的栈大小
+//每层用户函数调用会占用若干层evaluate_node的栈帧 默认的主线程栈不足以支撑最大调用深度
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
-    loop {
-        io::stdout().write_all(b">>> ")?;
-        io::stdout().flush()?;
+const USAGE: &str = "usage: tiny-interpreter [script [args...]]
+       tiny-interpreter -e <source> [args...]";
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        //去除input中存在的换行符
-        input = String::from(input.strip_suffix("\r\n").or(input.strip_suffix("\n")).unwrap_or(&input));
-        let mut lexer = Lexer::new(&input);
-
-        match parse_statement(& mut lexer) {
-            Ok(root) => {
-                //println!("{:?}", &root);
-                match evaluate_node(&root, env.clone()) {
-                    Ok(Some(result)) => {
-                        println!("ans = {}", format_value(&result));
-                    },
-                    Err(msg) => {
-                        println!("evaluate error: {}", msg);
-                    },
-                    _ => (),
-                }
-            }
-            Err(_) => {
-                println!("parse error");
-            }
-        }
-    }
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            set_stack_size(STACK_SIZE);
+            start(args)
+        })
+        .expect("can not spawn interpreter thread")
+        .join()
+        .unwrap_or(ExitCode::FAILURE)
 }
 
-fn format_value(val: &ASTValue) -> String {
-    match val {
-        ASTValue::Number(result) => {
-            format!("{}", result)
+//根据命令行参数选择运行方式
+//无参数: 终端中启动REPL 管道输入时把stdin当作脚本执行
+//-e source: 执行一段源码
+//script: 执行脚本文件 其余参数作为args传给脚本
+fn start(args: Vec<String>) -> ExitCode {
+    match args.first().map(String::as_str) {
+        Some("-e") => match args.get(1) {
+            Some(source) => run_script(source, &args[2..]),
+            None => usage(),
+        },
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
         },
-        ASTValue::Boolean(result) => {
-            format!("{}", result)
+        Some(flag) if flag.starts_with('-') => usage(),
+        Some(path) => match fs::read_to_string(path) {
+            Ok(source) => run_script(&source, &args[1..]),
+            Err(err) => {
+                eprintln!("can not read {}: {}", path, err);
+                ExitCode::FAILURE
+            },
         },
-        ASTValue::Function(_) => {
-            format!("lambda")
+        None if io::stdin().is_terminal() => match repl() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("console error: {}", err);
+                ExitCode::FAILURE
+            },
         },
-        ASTValue::Array(elements) => {
-            let mut result = String::from("[");
-            for element in elements.iter() {
-                result.push_str(&format_value(element));
-                result.push_str(", ")
+        None => {
+            let mut source = String::new();
+            match io::stdin().read_to_string(&mut source) {
+                Ok(_) => run_script(&source, &[]),
+                Err(err) => {
+                    eprintln!("can not read stdin: {}", err);
+                    ExitCode::FAILURE
+                },
             }
-            result.pop(); result.pop();
-            result.push_str("]");
-            result
         },
     }
 }
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
+
+//执行整段脚本 打印不带分号的语句的值 出错时立即停止并返回非零状态
+fn run_script(source: &str, args: &[String]) -> ExitCode {
+    let env = create_global_environment(args);
+    if run(source, 0, env, false).is_ok() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+//解析并依次执行源码中从第start个字符开始的所有语句 打印每条语句的值
+//交互模式下以ans = value的形式打印并把值注册为ans 出错时返回出错之前成功执行的语句数(解析出错时为0)
+//运行脚本时不注册ans 脚本可以自由使用这些名字
+//错误信息按整个source标注 之前输入中定义的函数出错时也能显示正确的源码行
+pub fn run(source: &str, start: usize, env: Rc<RefCell<Environment>>, interactive: bool) -> Result<(), usize> {
+    let input = &source[source.char_indices().nth(start).map_or(source.len(), |(i, _)| i)..];
+    match parse_program(&mut Lexer::with_offset(input, start)) {
+        Ok(nodes) => {
+            for (count, root) in nodes.iter().enumerate() {
+                match evaluate_statement(root, env.clone()) {
+                    Ok(Some(result)) => {
+                        if interactive {
+                            //最近一次的值可以通过ans继续使用
+                            regist_answer(env.clone(), result.clone());
+                            println!("ans = {}", result);
+                        } else {
+                            println!("{}", result);
+                        }
+                    },
+                    Ok(None) => (),
+                    Err(err) => {
+                        eprintln!("{}", err.render(source));
+                        return Err(count);
+                    },
+                }
+            }
+            Ok(())
+        }
+        Err(errors) => {
+            eprintln!("{}", render_errors(&errors, source));
+            Err(0)
+        }
+    }
+}
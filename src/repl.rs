@@ -0,0 +1,255 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use rustyline::Editor;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use crate::run;
+use crate::editor::{ReplHelper, history_path};
+use crate::gramma::token::Token;
+use crate::gramma::lexer::Lexer;
+use crate::gramma::parser::{parse_program, parse_statement, render_errors};
+use crate::gramma::ast::{ASTNode, ASTValue};
+use crate::gramma::environment::Environment;
+use crate::gramma::primitive::{create_global_environment, primitive_help, ANSWERS};
+use crate::gramma::evaluator::evaluate_statement;
+
+const COMMANDS: &str = "\
+:vars           list user variables and their values
+:type <expr>    evaluate expr and show the type of its value (variables are restored)
+:ast <expr>     show the syntax tree of expr
+:tokens <expr>  show the tokens of expr
+:reset          remove all user variables
+:load <file>    run a script file in the current session
+:save <file>    save the statements run in this session to a file
+:help [name]    show this list or the help of a function or variable";
+
+//源码中前count条语句的文本(包括结尾的;)
+fn leading_statements(source: &str, count: usize) -> String {
+    let mut lexer = Lexer::new(source);
+    for _ in 0..count {
+        if parse_statement(&mut lexer).is_err() {
+            break
+        }
+    }
+    let end = if count > 0 { lexer.prev_span().1 } else { 0 };
+    source.chars().take(end).collect::<String>().trim_end().to_string()
+}
+
+//交互会话
+struct Session {
+    env: Rc<RefCell<Environment>>,
+    //全局环境中内置的名字以及ans等保存结果的名字 :vars只列出用户定义的变量
+    builtins: HashSet<String>,
+    //本次会话成功执行过的语句 :save时写入文件
+    transcript: Vec<String>,
+    //本次会话的全部输入 语法树中的位置都相对于它 用于标注错误所在的源码行
+    source: String,
+}
+
+impl Session {
+    fn new() -> Self {
+        let env = create_global_environment(&[]);
+        let mut builtins: HashSet<String> = env.borrow().iter().map(|(name, _)| name.clone()).collect();
+        builtins.extend(ANSWERS.iter().map(|name| name.to_string()));
+        Session {
+            env,
+            builtins,
+            transcript: vec![],
+            source: String::new(),
+        }
+    }
+
+    //执行一段源码 记录其中成功执行的语句
+    //出错的语句以及之后没有执行的语句不记录 之前的语句已经生效 仍然记录下来
+    fn run(&mut self, source: &str) {
+        let start = self.source.chars().count();
+        self.source.push_str(source);
+        if !self.source.ends_with('\n') {
+            self.source.push('\n');
+        }
+        let executed = match run(&self.source, start, self.env.clone(), true) {
+            Ok(()) => source.trim_end().to_string(),
+            Err(count) => leading_statements(source, count),
+        };
+        if !executed.is_empty() {
+            self.transcript.push(executed);
+        }
+    }
+
+    //用户定义的变量 按名字排序
+    fn vars(&self) -> Vec<(String, ASTValue)> {
+        let env = self.env.borrow();
+        let mut vars: Vec<_> = env.iter()
+            .filter(|(name, _)| !self.builtins.contains(*name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        vars
+    }
+
+    //执行以:开头的命令
+    fn command(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        let (name, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        match (name, arg) {
+            (":vars", "") => {
+                for (name, value) in self.vars() {
+                    println!("{} = {}", name, value);
+                }
+            },
+            (":type", expr) if !expr.is_empty() => {
+                //查看类型不应改变会话中的变量 求值之后恢复全局环境原来的内容(包括函数调用中修改的全局变量)
+                //闭包捕获的局部环境以及最大调用深度等解释器设置不会恢复
+                let saved = self.env.borrow().clone();
+                let result = parse(expr).and_then(|roots| roots.iter().map(|root| {
+                    evaluate_statement(root, self.env.clone()).map_err(|err| format!("evaluate error: {}", err))
+                }).collect::<Result<Vec<_>, _>>());
+                *self.env.borrow_mut() = saved;
+                for value in result? {
+                    match value {
+                        Some(value) => println!("{}", value.type_name()),
+                        None => println!("void"),
+                    }
+                }
+            },
+            (":ast", expr) if !expr.is_empty() => {
+                for root in &parse(expr)? {
+                    println!("{:#?}", root);
+                }
+            },
+            (":tokens", expr) if !expr.is_empty() => {
+                let mut lexer = Lexer::new(expr);
+                while lexer.peek() != Token::End {
+                    let span = lexer.span();
+                    println!("{:?} {}..{}", lexer.next(), span.0, span.1);
+                }
+            },
+            (":reset", "") => {
+                //就地替换环境的内容 补全等持有同一个环境的地方随之更新
+                let fresh = create_global_environment(&[]);
+                std::mem::swap(&mut *self.env.borrow_mut(), &mut *fresh.borrow_mut());
+                self.transcript.clear();
+                self.source.clear();
+            },
+            (":load", path) if !path.is_empty() => {
+                let source = fs::read_to_string(path).map_err(|err| format!("command error: can not read {}: {}", path, err))?;
+                self.run(&source);
+            },
+            (":save", path) if !path.is_empty() => {
+                let mut source = self.transcript.join("\n");
+                source.push('\n');
+                fs::write(path, source).map_err(|err| format!("command error: can not write {}: {}", path, err))?;
+            },
+            (":help", "") => println!("{}", COMMANDS),
+            (":help", name) => match (primitive_help(name), self.env.borrow().get(name, false)) {
+                (Some(help), _) => println!("{}", help),
+                (None, Some(value)) => println!("{}: user defined {} = {}", name, value.type_name(), value),
+                (None, None) => return Err(format!("command error: no help for {}", name)),
+            },
+            (":vars" | ":type" | ":ast" | ":tokens" | ":reset" | ":load" | ":save", _) => {
+                return Err(format!("command error: wrong usage of {}, type :help for a list of commands", name))
+            },
+            _ => return Err(format!("command error: unknown command {}, type :help for a list of commands", name)),
+        }
+        Ok(())
+    }
+}
+
+//解析命令参数中的表达式
+fn parse(source: &str) -> Result<Vec<ASTNode>, String> {
+    parse_program(&mut Lexer::new(source)).map_err(|errors| render_errors(&errors, source))
+}
+
+pub fn repl() -> rustyline::Result<()> {
+    let mut session = Session::new();
+    //行编辑器 支持历史记录和tab补全
+    let mut editor: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper::new(session.env.clone())));
+    let history = history_path();
+    if let Some(path) = &history {
+        //第一次运行时历史文件还不存在
+        let _ = editor.load_history(path);
+    }
+    //累积的输入 括号未闭合时跨越多行
+    let mut input = String::new();
+
+    loop {
+        //续行时使用不同的提示符
+        let line = match editor.readline(if input.is_empty() { ">>> " } else { "... " }) {
+            Ok(line) => line,
+            //Ctrl-C 放弃当前输入
+            Err(ReadlineError::Interrupted) => {
+                input.clear();
+                continue;
+            },
+            //Ctrl-D 退出
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        };
+        //:开头的命令
+        if input.is_empty() && line.trim_start().starts_with(':') {
+            editor.add_history_entry(line.trim())?;
+            if let Err(msg) = session.command(&line) {
+                eprintln!("{}", msg);
+            }
+            continue;
+        }
+        input.push_str(&line);
+        input.push('\n');
+        if Lexer::new(&input).unclosed() {
+            continue;
+        }
+        //跨越多行的输入作为一条历史记录
+        if !input.trim().is_empty() {
+            editor.add_history_entry(input.trim_end())?;
+        }
+
+        session.run(&input);
+        input.clear();
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::Session;
+
+    #[test]
+    fn vars_skip_answers() {
+        let mut session = Session::new();
+        session.run("let x = 1; x + 1");
+        session.run("x * 3");
+        let names: Vec<_> = session.vars().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["x"]);
+    }
+
+    #[test]
+    fn type_keeps_bindings() {
+        let mut session = Session::new();
+        session.run("let x = 1; let count = 0; let f = () => { count += 1; count };");
+        for expr in [":type x = 5", ":type f()", ":type let y = 2", ":type x += 1; x"] {
+            assert!(session.command(expr).is_ok());
+        }
+        assert!(session.command(":type x = ").is_err());
+        let env = session.env.borrow();
+        assert_eq!(env.get("x", true).unwrap().to_string(), "1");
+        assert_eq!(env.get("count", true).unwrap().to_string(), "0");
+        assert!(env.get("y", true).is_none());
+        drop(env);
+
+        //闭包捕获的局部环境不会恢复 计数器仍然前进
+        session.run("let make_counter = () => { let n = 0; () => { n += 1; n } }; let k = make_counter();");
+        assert!(session.command(":type k()").is_ok());
+        session.run("let after = k();");
+        assert_eq!(session.env.borrow().get("after", true).unwrap().to_string(), "2");
+    }
+}